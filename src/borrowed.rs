@@ -0,0 +1,142 @@
+//! A tree that borrows its text from the input
+//!
+//! Keys, values and names borrow from the input unless they contain escape codes or more than
+//! single spaces between words, so parsing into this tree allocates little more than the
+//! tables and arrays themselves.
+
+use std::borrow::Cow;
+
+use {Acon, AconError, Kind, Map, ParserOptions, Path, Segment};
+
+/// An array of borrowed values
+pub type ArrayRef<'a> = Vec<AconRef<'a>>;
+
+/// A table of borrowed values
+pub type TableRef<'a> = Map<Cow<'a, str>, AconRef<'a>>;
+
+/// A value that borrows its text from the input
+///
+///  ```
+///  use acon::AconRef;
+///  use std::borrow::Cow;
+///  let input = "{ table\n  key some value\n  other (40)escaped)\n}";
+///  let result = AconRef::parse(input).unwrap();
+///  assert_eq!(result.path("table.key").unwrap().as_str(), Some("some value"));
+///  match *result.path("table.key").unwrap() {
+///      AconRef::String(Cow::Borrowed(_)) => {}
+///      _ => panic!("the value is borrowed from the input"),
+///  }
+///  assert_eq!(result.into_owned(), input.parse().unwrap());
+///  ```
+///
+#[derive(PartialEq, Clone, Debug)]
+pub enum AconRef<'a> {
+	/// An array of values
+	Array(ArrayRef<'a>),
+	/// A string
+	String(Cow<'a, str>),
+	/// A table of keys mapped to values
+	Table(TableRef<'a>),
+}
+
+impl<'a> AconRef<'a> {
+	/// Parse a string into a tree borrowing from it
+	pub fn parse(input: &'a str) -> Result<AconRef<'a>, AconError> {
+		ParserOptions::new().parse_ref(input)
+	}
+
+	/// Copy all text into an owned tree
+	pub fn into_owned(self) -> Acon {
+		match self {
+			AconRef::Array(array) => Acon::Array(array.into_iter().map(AconRef::into_owned).collect()),
+			AconRef::String(string) => Acon::String(string.into_owned()),
+			AconRef::Table(table) => {
+				Acon::Table(table.into_iter().map(|(key, value)| (key.into_owned(), value.into_owned())).collect())
+			}
+		}
+	}
+
+	/// The kind of the value
+	pub fn kind(&self) -> Kind {
+		match *self {
+			AconRef::Array(_) => Kind::Array,
+			AconRef::String(_) => Kind::String,
+			AconRef::Table(_) => Kind::Table,
+		}
+	}
+
+	/// Get the array if this is an array
+	pub fn as_array(&self) -> Option<&ArrayRef<'a>> {
+		match *self {
+			AconRef::Array(ref array) => Some(array),
+			_ => None,
+		}
+	}
+
+	/// Get the string if this is a string
+	pub fn as_str(&self) -> Option<&str> {
+		match *self {
+			AconRef::String(ref string) => Some(string),
+			_ => None,
+		}
+	}
+
+	/// Get the table if this is a table
+	pub fn as_table(&self) -> Option<&TableRef<'a>> {
+		match *self {
+			AconRef::Table(ref table) => Some(table),
+			_ => None,
+		}
+	}
+
+	/// Retrieve a reference to an entry
+	pub fn get(&self, key: &str) -> Option<&AconRef<'a>> {
+		match *self {
+			AconRef::Array(ref array) => key.parse::<usize>().ok().and_then(|index| array.get(index)),
+			AconRef::String(_) => None,
+			AconRef::Table(ref table) => table.get(key),
+		}
+	}
+
+	/// Retrieve a reference to an entry via its path, as with `Acon::path`
+	pub fn path<P: Into<Path>>(&self, path: P) -> Option<&AconRef<'a>> {
+		let mut current = self;
+		for segment in &path.into() {
+			current = match *current {
+				AconRef::Array(ref array) => match *segment {
+					Segment::Index(index) => array.get(index)?,
+					Segment::Key(ref key) => current.get(key)?,
+					Segment::Append => return None,
+				},
+				AconRef::String(_) => return None,
+				AconRef::Table(ref table) => table.get(segment.key().as_ref())?,
+			};
+		}
+		Some(current)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn borrows_from_input() {
+		let input = "{ t(46)able\n\tkey a  b\n\tplain a b\n}\n[ list\n\titem\n\t{ named\n\t\tx y\n\t}\n]\n";
+		let result = AconRef::parse(input).unwrap();
+		let table = result.get("t.able").unwrap().as_table().unwrap();
+		assert!(matches!(table.keys().next(), Some(&Cow::Borrowed("key"))));
+		assert!(matches!(table["key"], AconRef::String(Cow::Owned(ref value)) if value == "a b"));
+		assert!(matches!(table["plain"], AconRef::String(Cow::Borrowed("a b"))));
+		assert_eq!(result.path("list.1.named.x").unwrap().as_str(), Some("y"));
+		assert_eq!(result.path("list.0").unwrap().kind(), Kind::String);
+		assert_eq!(result.clone().into_owned(), input.parse::<Acon>().unwrap());
+	}
+
+	#[test]
+	fn same_errors() {
+		for input in &["a b\na c", "{\n]", "[\n", "}", "{ a\n}\n{ a\n}"] {
+			assert_eq!(AconRef::parse(input).unwrap_err(), input.parse::<Acon>().unwrap_err());
+		}
+	}
+}
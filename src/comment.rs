@@ -0,0 +1,280 @@
+//! Keeping the comments of a document
+//!
+//! Parsing into `Acon` drops every `#` line. Parsing into `Commented` instead attaches each
+//! comment to the path of the entry or element that follows it. Comments that no value
+//! follows end a table or array, or the document, and are attached to that table or array,
+//! or to the empty path. Writing a `Commented` puts the comments back in those places.
+//!
+//! Paths in the comments name an entry of a table by a key, even when the key is written
+//! like an index, as `0` in `t.0` where `t` is a table. The lookups and writing match such
+//! indices with the keys.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use parse::Builder;
+use {write_root, Acon, AconError, Event, Kind, ParserOptions, Path, PullParser, Segment};
+
+/// The comments attached to a value
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Comments {
+	/// The comments on the lines before the value
+	pub leading: Vec<String>,
+	/// The comments after the last value of a table or array, before it is closed
+	pub trailing: Vec<String>,
+}
+
+/// A tree along with the comments of the document it was parsed from
+///
+///  ```
+///  use acon::{Acon, Commented, Path};
+///  let input = "# Where to listen\n{ server\n\t# The public name\n\thost example.com\n\t# More to come\n}\n";
+///  let mut result = input.parse::<Commented>().unwrap();
+///  assert_eq!(result.comments[&Path::from("server.host")].leading, ["The public name"]);
+///  assert_eq!(result.comments[&Path::from("server")].trailing, ["More to come"]);
+///
+///  result.value.set_path("server.host", Acon::String("example.org".to_string())).unwrap();
+///  assert_eq!(result.to_string(), input.replace("example.com", "example.org"));
+///  ```
+///
+/// Comments whose path is no longer in the tree are not written.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Commented {
+	/// The tree, which is the same as parsing the document into `Acon`
+	pub value: Acon,
+	/// The comments by the path of the value they are attached to, where every segment in a
+	/// table is a key
+	pub comments: BTreeMap<Path, Comments>,
+}
+
+impl Commented {
+	/// Retrieve the comments attached to the value at a path
+	///
+	///  ```
+	///  use acon::Commented;
+	///  let result = "{ t\n\t# The first\n\t0 zero\n}\n".parse::<Commented>().unwrap();
+	///  assert_eq!(result.get("t.0").unwrap().leading, ["The first"]);
+	///  ```
+	///
+	pub fn get<P: Into<Path>>(&self, path: P) -> Option<&Comments> {
+		self.comments.get(&concrete(&self.value, &path.into()))
+	}
+
+	/// Retrieve the comments attached to the value at a path for changing them, attaching
+	/// empty comments if there are none
+	pub fn get_mut<P: Into<Path>>(&mut self, path: P) -> &mut Comments {
+		let path = concrete(&self.value, &path.into());
+		self.comments.entry(path).or_default()
+	}
+}
+
+impl FromStr for Commented {
+	type Err = AconError;
+
+	/// Parse a string into a tree and its comments
+	fn from_str(input: &str) -> Result<Commented, AconError> {
+		let mut builder = Builder::<Acon>::new(ParserOptions::new());
+		let mut comments = BTreeMap::new();
+		let mut pending = Vec::new();
+		// The path of every open table and array, along with its number of elements
+		let mut open = vec![(Path::new(), Kind::Table, 0)];
+		for event in PullParser::new(input) {
+			let (span, event) = event?;
+			match event {
+				Event::Comment(ref text) => pending.push(text.to_string()),
+				Event::Entry(ref key, _) => {
+					let path = open[open.len() - 1].0.join(key.as_ref());
+					attach_leading(&mut comments, path, &mut pending);
+				}
+				Event::ArrayItem(_) => {
+					let path = next_element(&mut open);
+					attach_leading(&mut comments, path, &mut pending);
+				}
+				Event::TableStart(ref name) | Event::ArrayStart(ref name) => {
+					let kind = if let Event::TableStart(_) = event { Kind::Table } else { Kind::Array };
+					let path = if open[open.len() - 1].1 == Kind::Array {
+						let element = next_element(&mut open);
+						attach_leading(&mut comments, element.clone(), &mut pending);
+						if name.is_empty() { element } else { element.join(name.as_ref()) }
+					} else {
+						let path = open[open.len() - 1].0.join(name.as_ref());
+						attach_leading(&mut comments, path.clone(), &mut pending);
+						path
+					};
+					open.push((path, kind, 0));
+				}
+				Event::End => {
+					if let Some((path, _, _)) = open.pop() {
+						attach_trailing(&mut comments, path, &mut pending);
+					}
+				}
+				Event::CloseAll => {
+					if open.len() > 1 {
+						let path = open[open.len() - 1].0.clone();
+						attach_trailing(&mut comments, path, &mut pending);
+						open.truncate(1);
+					}
+				}
+			}
+			builder.event(span, event)?;
+		}
+		attach_trailing(&mut comments, Path::new(), &mut pending);
+		Ok(Commented { value: builder.finish()?, comments })
+	}
+}
+
+impl fmt::Display for Commented {
+	/// Write the tree as `Display` for `Acon` does, with the comments in their places
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let comments = self.comments.iter()
+			.map(|(path, comments)| (concrete(&self.value, path), comments.clone()))
+			.collect();
+		write_root(&self.value, f, &mut Position::with(&comments))
+	}
+}
+
+/// The path that names every entry of a table in the value by its key
+fn concrete(value: &Acon, path: &Path) -> Path {
+	let mut current = Some(value);
+	let mut concrete = Path::new();
+	for segment in path {
+		let segment = match current {
+			Some(&Acon::Table(_)) => Segment::Key(segment.key().into_owned()),
+			_ => segment.clone(),
+		};
+		current = current.and_then(|value| value.get_segment(&segment));
+		concrete.push(segment);
+	}
+	concrete
+}
+
+/// The path of the next element of the innermost array
+fn next_element(open: &mut [(Path, Kind, usize)]) -> Path {
+	let top = open.len() - 1;
+	let path = open[top].0.join(open[top].2);
+	open[top].2 += 1;
+	path
+}
+
+fn attach_leading(comments: &mut BTreeMap<Path, Comments>, path: Path, pending: &mut Vec<String>) {
+	if !pending.is_empty() {
+		comments.entry(path).or_default().leading.append(pending);
+	}
+}
+
+fn attach_trailing(comments: &mut BTreeMap<Path, Comments>, path: Path, pending: &mut Vec<String>) {
+	if !pending.is_empty() {
+		comments.entry(path).or_default().trailing.append(pending);
+	}
+}
+
+/// Where the serializer is in the tree, which is only tracked when there are comments to write
+pub(crate) struct Position<'a> {
+	comments: Option<&'a BTreeMap<Path, Comments>>,
+	path: Path,
+}
+
+impl<'a> Position<'a> {
+	pub(crate) fn without_comments() -> Position<'a> {
+		Position { comments: None, path: Path::new() }
+	}
+
+	fn with(comments: &'a BTreeMap<Path, Comments>) -> Position<'a> {
+		Position { comments: Some(comments), path: Path::new() }
+	}
+
+	pub(crate) fn enter<S: Into<Segment>>(&mut self, segment: S) {
+		if self.comments.is_some() {
+			self.path.push(segment);
+		}
+	}
+
+	pub(crate) fn leave(&mut self) {
+		self.path.pop();
+	}
+
+	/// Write the comments before the current value
+	pub(crate) fn leading(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+		match self.comments.and_then(|comments| comments.get(&self.path)) {
+			Some(comments) => write_comments(&comments.leading, f, depth),
+			None => Ok(()),
+		}
+	}
+
+	/// Write the comments that end the current table or array
+	pub(crate) fn trailing(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+		match self.comments.and_then(|comments| comments.get(&self.path)) {
+			Some(comments) => write_comments(&comments.trailing, f, depth),
+			None => Ok(()),
+		}
+	}
+}
+
+/// Write each line of each comment as a `#` line
+fn write_comments(comments: &[String], f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+	for line in comments.iter().flat_map(|comment| comment.split('\n')) {
+		for _ in 0..depth {
+			f.write_str("\t")?;
+		}
+		let line = line.trim();
+		if line.is_empty() { f.write_str("#\n")? } else { writeln!(f, "# {}", line)? }
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn attaches_comments() {
+		let input = "# one\n# two\nkey value\n[ list\n\t# first\n\tx\n\t# named\n\t{ n\n\t\t# inner\n\t\ty z\n\t\t# end of n\n\t}\n\
+		             \t# end of list\n]\n{ a\n\t{ b\n\t\t# before dollar\n$\n# the end\n";
+		let result = input.parse::<Commented>().unwrap();
+		let leading = |path: &str| result.comments[&Path::from(path)].leading.clone();
+		let trailing = |path: &str| result.comments[&Path::from(path)].trailing.clone();
+		assert_eq!(leading("key"), ["one", "two"]);
+		assert_eq!(leading("list.0"), ["first"]);
+		assert_eq!(leading("list.1"), ["named"]);
+		assert_eq!(leading("list.1.n.y"), ["inner"]);
+		assert_eq!(trailing("list.1.n"), ["end of n"]);
+		assert_eq!(trailing("list"), ["end of list"]);
+		assert_eq!(trailing("a.b"), ["before dollar"]);
+		assert_eq!(result.comments[&Path::new()].trailing, ["the end"]);
+		assert_eq!(result.comments.len(), 8);
+		assert_eq!(result.value, input.parse::<Acon>().unwrap());
+	}
+
+	#[test]
+	fn writes_comments() {
+		let input = "# one\nkey value\n[ list\n\t# empty element\n\n\t# named\n\t{ n\n\t\ty z\n\t\t# end of n\n\t}\n]\n# the end\n";
+		let result = input.parse::<Commented>().unwrap();
+		assert_eq!(result.to_string(), input);
+		assert_eq!(result.to_string().parse::<Commented>().unwrap(), result);
+
+		let mut result = result;
+		result.value.remove_path("key");
+		result.comments.insert(Path::from("list.1.n.y"), Comments {
+			leading: vec!["two\nlines".to_string(), "".to_string()],
+			trailing: vec![],
+		});
+		assert_eq!(result.to_string(), "[ list\n\t# empty element\n\n\t# named\n\t{ n\n\t\t# two\n\t\t# lines\n\t\t#\n\t\ty z\n\t\t# end of n\n\t}\n]\n# the end\n");
+		assert_eq!(result.value.to_string(), "[ list\n\n\t{ n\n\t\ty z\n\t}\n]\n");
+	}
+
+	#[test]
+	fn numeric_keys() {
+		let input = "[ l\n\t# first\n\tx\n]\n{ t\n\t# zero\n\t0 a\n\t1 b\n}\n";
+		let mut result = input.parse::<Commented>().unwrap();
+		assert_eq!(result.get("t.0").unwrap().leading, ["zero"]);
+		assert_eq!(result.get("l.0").unwrap().leading, ["first"]);
+		assert_eq!(result.get(Path::from(vec![Segment::from("t"), Segment::from("0")])), result.get("t.0"));
+		assert_eq!(result.get("t.1"), None);
+
+		result.get_mut("t.1").leading.push("one".to_string());
+		result.comments.insert(Path::from("l.0"), Comments { leading: vec!["replaced".to_string()], trailing: vec![] });
+		result.comments.insert(Path::from("t.2"), Comments { leading: vec!["gone".to_string()], trailing: vec![] });
+		assert_eq!(result.to_string(), "[ l\n\t# replaced\n\tx\n]\n{ t\n\t# zero\n\t0 a\n\t# one\n\t1 b\n}\n");
+	}
+}
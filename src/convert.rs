@@ -0,0 +1,331 @@
+//! Conversions from strings to scalars
+//!
+//! Every leaf in ACON is a string. This module interprets those strings as numbers,
+//! booleans, durations and byte sizes.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use {Acon, Kind, Path};
+
+/// Errors that come about when converting a value
+#[derive(PartialEq, Clone, Debug)]
+pub enum ConvertError {
+	/// There is no value at the path
+	Missing {
+		/// The path that was looked up
+		path: String,
+	},
+	/// The value is a table or an array instead of a string
+	NotAString {
+		/// The path of the value, empty if the value was converted directly
+		path: String,
+		/// The kind of the value
+		actual: Kind,
+	},
+	/// The string can not be converted
+	Invalid {
+		/// The path of the value, empty if the value was converted directly
+		path: String,
+		/// The string that was converted
+		raw: String,
+		/// Explanation of why the conversion failed
+		message: String,
+	},
+}
+
+impl fmt::Display for ConvertError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ConvertError::Missing { ref path } => write!(f, "{}: no such value", path),
+			ConvertError::NotAString { ref path, actual } => {
+				write!(f, "{}: expected a string, found {}", display_path(path), actual)
+			}
+			ConvertError::Invalid { ref path, ref raw, ref message } => {
+				write!(f, "{}: cannot convert {:?}: {}", display_path(path), raw, message)
+			}
+		}
+	}
+}
+
+impl std::error::Error for ConvertError {}
+
+fn display_path(path: &str) -> &str {
+	if path.is_empty() { "value" } else { path }
+}
+
+/// Parse a boolean, which is one of `true`, `yes`, `on`, `false`, `no` and `off`, in any case.
+///
+///  ```
+///  assert_eq!(acon::parse_bool("Yes"), Some(true));
+///  assert_eq!(acon::parse_bool("off"), Some(false));
+///  assert_eq!(acon::parse_bool("1"), None);
+///  ```
+///
+pub fn parse_bool(string: &str) -> Option<bool> {
+	match string.to_lowercase().as_str() {
+		"true" | "yes" | "on" => Some(true),
+		"false" | "no" | "off" => Some(false),
+		_ => None,
+	}
+}
+
+/// Parse a human-readable duration, which is one or more numbers each followed by a unit.
+///
+/// The units are `ns`, `us`, `ms`, `s`, `m`, `h` and `d`. Numbers may have a fraction.
+///
+///  ```
+///  use std::time::Duration;
+///  assert_eq!(acon::parse_duration("30s"), Some(Duration::from_secs(30)));
+///  assert_eq!(acon::parse_duration("1h 30m"), Some(Duration::from_secs(5400)));
+///  assert_eq!(acon::parse_duration("1.5ms"), Some(Duration::from_micros(1500)));
+///  assert_eq!(acon::parse_duration("30"), None);
+///  ```
+///
+pub fn parse_duration(string: &str) -> Option<Duration> {
+	let mut total = Duration::new(0, 0);
+	let mut rest = string.trim();
+	if rest.is_empty() {
+		return None;
+	}
+	while !rest.is_empty() {
+		let (number, unit, remainder) = split_quantity(rest)?;
+		let nanoseconds: u64 = match unit {
+			"ns" => 1,
+			"us" | "µs" => 1_000,
+			"ms" => 1_000_000,
+			"s" => 1_000_000_000,
+			"m" => 60 * 1_000_000_000,
+			"h" => 60 * 60 * 1_000_000_000,
+			"d" => 24 * 60 * 60 * 1_000_000_000,
+			_ => return None,
+		};
+		total = total.checked_add(scale(number, nanoseconds).map(Duration::from_nanos)?)?;
+		rest = remainder.trim_start();
+	}
+	Some(total)
+}
+
+/// Parse a byte size, which is a number optionally followed by a unit.
+///
+/// The units are `B`, the decimal units `kB`, `MB`, `GB`, `TB` and `PB`, and the binary units
+/// `KiB`, `MiB`, `GiB`, `TiB` and `PiB`. Units are not case-sensitive, and `K`, `M`, `G`, `T` and
+/// `P` are short for the decimal units.
+///
+///  ```
+///  assert_eq!(acon::parse_byte_size("10MiB"), Some(10 * 1024 * 1024));
+///  assert_eq!(acon::parse_byte_size("1.5 kB"), Some(1500));
+///  assert_eq!(acon::parse_byte_size("512"), Some(512));
+///  ```
+///
+pub fn parse_byte_size(string: &str) -> Option<u64> {
+	let (number, unit, rest) = split_quantity(string.trim())?;
+	if !rest.is_empty() {
+		return None;
+	}
+	let multiplier: u64 = match unit.to_lowercase().as_str() {
+		"" | "b" => 1,
+		"k" | "kb" => 1_000,
+		"m" | "mb" => 1_000_000,
+		"g" | "gb" => 1_000_000_000,
+		"t" | "tb" => 1_000_000_000_000,
+		"p" | "pb" => 1_000_000_000_000_000,
+		"kib" => 1 << 10,
+		"mib" => 1 << 20,
+		"gib" => 1 << 30,
+		"tib" => 1 << 40,
+		"pib" => 1 << 50,
+		_ => return None,
+	};
+	scale(number, multiplier)
+}
+
+/// Split a number followed by an optional unit off the start of a string. The unit may be
+/// separated from the number by whitespace.
+fn split_quantity(string: &str) -> Option<(&str, &str, &str)> {
+	let number_length = string.find(|character: char| !character.is_ascii_digit() && character != '.')
+		.unwrap_or(string.len());
+	let (number, rest) = string.split_at(number_length);
+	if number.is_empty() {
+		return None;
+	}
+	let rest = rest.trim_start();
+	let unit_length = rest.find(|character: char| character.is_ascii_digit() || character.is_whitespace())
+		.unwrap_or(rest.len());
+	let (unit, rest) = rest.split_at(unit_length);
+	Some((number, unit, rest))
+}
+
+/// Multiply a decimal number by an integer, rounding down
+fn scale(number: &str, multiplier: u64) -> Option<u64> {
+	let mut parts = number.splitn(2, '.');
+	let whole = parts.next().unwrap_or("");
+	let fraction = parts.next().unwrap_or("");
+	if fraction.contains('.') || (whole.is_empty() && fraction.is_empty()) {
+		return None;
+	}
+	let whole = if whole.is_empty() { 0 } else { whole.parse::<u64>().ok()? };
+	// Digits past the precision of a u64 can not change the rounded down result
+	let (numerator, denominator) = fraction.bytes().take(19)
+		.fold((0u128, 1u128), |(numerator, denominator), digit| {
+			(numerator * 10 + u128::from(digit - b'0'), denominator * 10)
+		});
+	let total = u128::from(whole) * u128::from(multiplier)
+		+ numerator * u128::from(multiplier) / denominator;
+	u64::try_from(total).ok()
+}
+
+impl Acon {
+	/// Parse this string into any type implementing `FromStr`
+	///
+	///  ```
+	///  use acon::Acon;
+	///  let result = "port 8080".parse::<Acon>().unwrap();
+	///  assert_eq!(result.path("port").unwrap().parse_as::<u16>(), Ok(8080));
+	///  ```
+	///
+	pub fn parse_as<T>(&self) -> Result<T, ConvertError>
+		where T: FromStr, T::Err: fmt::Display {
+		self.convert("", |string| string.parse::<T>().map_err(|error| error.to_string()))
+	}
+
+	/// Parse the string at the path into any type implementing `FromStr`
+	///
+	///  ```
+	///  use acon::Acon;
+	///  let result = "{ server\n  port 80x\n}".parse::<Acon>().unwrap();
+	///  let error = result.path_as::<u16>("server.port").unwrap_err();
+	///  assert_eq!(error.to_string(), r#"server.port: cannot convert "80x": invalid digit found in string"#);
+	///  ```
+	///
+	// The path is an `impl Into<Path>` so that `path_as::<T>` can still name only the type
+	pub fn path_as<T>(&self, path: impl Into<Path>) -> Result<T, ConvertError>
+		where T: FromStr, T::Err: fmt::Display {
+		self.convert_path(path.into(), |string| string.parse::<T>().map_err(|error| error.to_string()))
+	}
+
+	/// Parse this string as a boolean, see `parse_bool`
+	pub fn parse_bool(&self) -> Result<bool, ConvertError> {
+		self.convert("", to_bool)
+	}
+
+	/// Parse this string as a duration, see `parse_duration`
+	pub fn parse_duration(&self) -> Result<Duration, ConvertError> {
+		self.convert("", to_duration)
+	}
+
+	/// Parse this string as a byte size, see `parse_byte_size`
+	pub fn parse_byte_size(&self) -> Result<u64, ConvertError> {
+		self.convert("", to_byte_size)
+	}
+
+	/// Parse the string at the path as a boolean, see `parse_bool`
+	pub fn path_bool<P: Into<Path>>(&self, path: P) -> Result<bool, ConvertError> {
+		self.convert_path(path.into(), to_bool)
+	}
+
+	/// Parse the string at the path as a duration, see `parse_duration`
+	pub fn path_duration<P: Into<Path>>(&self, path: P) -> Result<Duration, ConvertError> {
+		self.convert_path(path.into(), to_duration)
+	}
+
+	/// Parse the string at the path as a byte size, see `parse_byte_size`
+	pub fn path_byte_size<P: Into<Path>>(&self, path: P) -> Result<u64, ConvertError> {
+		self.convert_path(path.into(), to_byte_size)
+	}
+
+	fn convert_path<T, F>(&self, path: Path, conversion: F) -> Result<T, ConvertError>
+		where F: FnOnce(&str) -> Result<T, String> {
+		match self.path(&path) {
+			Some(acon) => acon.convert(&path.to_string(), conversion),
+			None => Err(ConvertError::Missing { path: path.to_string() }),
+		}
+	}
+
+	fn convert<T, F>(&self, path: &str, conversion: F) -> Result<T, ConvertError>
+		where F: FnOnce(&str) -> Result<T, String> {
+		let string = self.try_str().map_err(|error| {
+			ConvertError::NotAString { path: path.to_string(), actual: error.actual }
+		})?;
+		conversion(string).map_err(|message| {
+			ConvertError::Invalid { path: path.to_string(), raw: string.to_string(), message }
+		})
+	}
+}
+
+fn to_bool(string: &str) -> Result<bool, String> {
+	parse_bool(string).ok_or_else(|| "expected one of true, false, yes, no, on, or off".to_string())
+}
+
+fn to_duration(string: &str) -> Result<Duration, String> {
+	parse_duration(string).ok_or_else(|| "expected a duration such as 30s or 5m".to_string())
+}
+
+fn to_byte_size(string: &str) -> Result<u64, String> {
+	parse_byte_size(string).ok_or_else(|| "expected a byte size such as 512, 10MB or 10MiB".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use Segment;
+
+	#[test]
+	fn durations() {
+		assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+		assert_eq!(parse_duration("1d2h3m4s"), Some(Duration::from_secs(93_784)));
+		assert_eq!(parse_duration("250us"), Some(Duration::from_micros(250)));
+		assert_eq!(parse_duration(".5s"), Some(Duration::from_millis(500)));
+		assert_eq!(parse_duration(""), None);
+		assert_eq!(parse_duration("5 minutes"), None);
+		assert_eq!(parse_duration("1.2.3s"), None);
+		assert_eq!(parse_duration("99999999999999999999d"), None);
+	}
+
+	#[test]
+	fn byte_sizes() {
+		assert_eq!(parse_byte_size("10MiB"), Some(10_485_760));
+		assert_eq!(parse_byte_size("10mb"), Some(10_000_000));
+		assert_eq!(parse_byte_size("2 GiB"), Some(2_147_483_648));
+		assert_eq!(parse_byte_size("0.5KiB"), Some(512));
+		assert_eq!(parse_byte_size("1B"), Some(1));
+		assert_eq!(parse_byte_size("1 2"), None);
+		assert_eq!(parse_byte_size("MiB"), None);
+		assert_eq!(parse_byte_size("20000PiB"), None);
+	}
+
+	#[test]
+	fn booleans() {
+		for string in &["true", "TRUE", "yes", "On"] {
+			assert_eq!(parse_bool(string), Some(true));
+		}
+		for string in &["false", "No", "OFF"] {
+			assert_eq!(parse_bool(string), Some(false));
+		}
+		assert_eq!(parse_bool("maybe"), None);
+	}
+
+	#[test]
+	fn conversion_errors() {
+		let acon = "{ server\n  port 80\n  verbose perhaps\n  timeout 30s\n  limit 1KiB\n}".parse::<Acon>().unwrap();
+		assert_eq!(acon.path_as::<u16>("server.port"), Ok(80));
+		assert_eq!(acon.path_as::<f32>("server.port"), Ok(80.0));
+		assert_eq!(acon.path_duration("server.timeout"), Ok(Duration::from_secs(30)));
+		assert_eq!(acon.path_byte_size("server.limit"), Ok(1024));
+		let port = Path::from("server").join(Segment::Key("port".to_string()));
+		assert_eq!(acon.path_as::<u16>(&port), Ok(80));
+		assert_eq!(acon.path_as::<u16>(port.join(Segment::Index(0))),
+		           Err(ConvertError::Missing { path: "server.port.0".to_string() }));
+		assert_eq!(acon.path_as::<u16>("server.missing"),
+		           Err(ConvertError::Missing { path: "server.missing".to_string() }));
+		assert_eq!(acon.path_as::<u16>("server"),
+		           Err(ConvertError::NotAString { path: "server".to_string(), actual: Kind::Table }));
+		let error = acon.path_bool("server.verbose").unwrap_err();
+		assert_eq!(error.to_string(),
+		           "server.verbose: cannot convert \"perhaps\": expected one of true, false, yes, no, on, or off");
+		assert_eq!(acon.path("server.verbose").unwrap().parse_bool().unwrap_err().to_string(),
+		           "value: cannot convert \"perhaps\": expected one of true, false, yes, no, on, or off");
+	}
+}
@@ -0,0 +1,465 @@
+//! Deserialize Rust data structures from ACON
+//!
+//! Tables map to structs and maps, arrays map to sequences and tuples, and strings are
+//! parsed into whatever primitive is asked for. Booleans are parsed by `parse_bool`. An
+//! empty string deserializes to `None` or `()`.
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Unexpected, Visitor};
+use serde::de::value::BorrowedStrDeserializer;
+use std::slice;
+
+use map;
+use {escape, parse_bool, Acon, SerdeError};
+
+/// Deserialize an instance of `T` from ACON text
+///
+///  ```
+///  #[macro_use]
+///  extern crate serde_derive;
+///  extern crate acon;
+///
+///  #[derive(Deserialize, Debug, PartialEq)]
+///  struct Config {
+///      name: String,
+///      port: u16,
+///      hosts: Vec<String>,
+///  }
+///
+///  let input = "name web\nport 80\n[ hosts\n  a.example.com\n  b.example.com\n]";
+///  let config: Config = acon::from_str(input).unwrap();
+///  assert_eq!(config.port, 80);
+///  assert_eq!(config.hosts[1], "b.example.com");
+///  ```
+///
+pub fn from_str<T: DeserializeOwned>(string: &str) -> Result<T, SerdeError> {
+	let acon = string.parse::<Acon>().map_err(SerdeError::Parse)?;
+	from_acon(&acon)
+}
+
+/// Deserialize an instance of `T` from an ACON value
+pub fn from_acon<'de, T: de::Deserialize<'de>>(acon: &'de Acon) -> Result<T, SerdeError> {
+	T::deserialize(Deserializer::new(acon))
+}
+
+/// A deserializer reading from an ACON value
+pub struct Deserializer<'de> {
+	value: &'de Acon,
+	path: String,
+}
+
+impl<'de> Deserializer<'de> {
+	/// Create a deserializer for the value
+	pub fn new(value: &'de Acon) -> Deserializer<'de> {
+		Deserializer { value, path: String::new() }
+	}
+
+	fn child(&self, value: &'de Acon, key: &str) -> Deserializer<'de> {
+		Deserializer { value, path: child_path(&self.path, key) }
+	}
+
+	fn string(&self) -> Result<&'de str, SerdeError> {
+		match *self.value {
+			Acon::String(ref string) => Ok(string),
+			_ => Err(self.unexpected("a string")),
+		}
+	}
+
+	fn unexpected(&self, expected: &str) -> SerdeError {
+		<SerdeError as de::Error>::invalid_type(unexpected(self.value), &expected).at(&self.path)
+	}
+}
+
+fn child_path(path: &str, key: &str) -> String {
+	if path.is_empty() {
+		escape(key)
+	} else {
+		format!("{}.{}", path, escape(key))
+	}
+}
+
+fn unexpected(value: &Acon) -> Unexpected<'_> {
+	match *value {
+		Acon::Array(_) => Unexpected::Seq,
+		Acon::String(ref string) => Unexpected::Str(string),
+		Acon::Table(_) => Unexpected::Map,
+	}
+}
+
+macro_rules! deserialize_parse {
+	( $( $method:ident => $visit:ident ),* ) => {
+		$(
+			fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+				let string = self.string()?;
+				match string.parse() {
+					Ok(value) => visitor.$visit(value),
+					Err(error) => Err(SerdeError::Message {
+						path: self.path.clone(),
+						message: format!("cannot convert {:?}: {}", string, error),
+					}),
+				}
+			}
+		)*
+	};
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+	type Error = SerdeError;
+
+	fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		match *self.value {
+			Acon::Array(_) => self.deserialize_seq(visitor),
+			Acon::String(ref string) => visitor.visit_borrowed_str(string),
+			Acon::Table(_) => self.deserialize_map(visitor),
+		}
+	}
+
+	fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		let string = self.string()?;
+		match parse_bool(string) {
+			Some(value) => visitor.visit_bool(value),
+			None => Err(<SerdeError as de::Error>::invalid_value(Unexpected::Str(string), &"a boolean").at(&self.path)),
+		}
+	}
+
+	deserialize_parse! {
+		deserialize_i8 => visit_i8, deserialize_i16 => visit_i16,
+		deserialize_i32 => visit_i32, deserialize_i64 => visit_i64,
+		deserialize_i128 => visit_i128, deserialize_u8 => visit_u8,
+		deserialize_u16 => visit_u16, deserialize_u32 => visit_u32,
+		deserialize_u64 => visit_u64, deserialize_u128 => visit_u128,
+		deserialize_f32 => visit_f32, deserialize_f64 => visit_f64,
+		deserialize_char => visit_char
+	}
+
+	fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		visitor.visit_borrowed_str(self.string()?)
+	}
+
+	fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		self.deserialize_str(visitor)
+	}
+
+	fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		match *self.value {
+			Acon::String(ref string) => visitor.visit_borrowed_bytes(string.as_bytes()),
+			_ => self.deserialize_seq(visitor),
+		}
+	}
+
+	fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		self.deserialize_bytes(visitor)
+	}
+
+	fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		match *self.value {
+			Acon::String(ref string) if string.is_empty() => visitor.visit_none(),
+			_ => visitor.visit_some(self),
+		}
+	}
+
+	fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		match *self.value {
+			Acon::String(ref string) if string.is_empty() => visitor.visit_unit(),
+			_ => Err(self.unexpected("an empty string")),
+		}
+	}
+
+	fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V)
+		-> Result<V::Value, SerdeError> {
+		self.deserialize_unit(visitor)
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V)
+		-> Result<V::Value, SerdeError> {
+		visitor.visit_newtype_struct(self)
+	}
+
+	fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		match *self.value {
+			Acon::Array(ref array) => {
+				visitor.visit_seq(SeqAccess { elements: array.iter().enumerate(), parent: &self })
+			}
+			_ => Err(self.unexpected("an array")),
+		}
+	}
+
+	fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+		self.deserialize_seq(visitor)
+	}
+
+	fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V)
+		-> Result<V::Value, SerdeError> {
+		self.deserialize_seq(visitor)
+	}
+
+	fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		match *self.value {
+			Acon::Table(ref table) => {
+				visitor.visit_map(MapAccess { entries: table.iter(), value: None, parent: &self })
+			}
+			_ => Err(self.unexpected("a table")),
+		}
+	}
+
+	fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str],
+	                                       visitor: V) -> Result<V::Value, SerdeError> {
+		self.deserialize_map(visitor)
+	}
+
+	fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str],
+	                                     visitor: V) -> Result<V::Value, SerdeError> {
+		match *self.value {
+			Acon::String(ref string) => visitor.visit_enum(string.as_str().into_deserializer()),
+			Acon::Table(ref table) if table.len() == 1 => {
+				let (variant, value) = table.iter().next().expect("table has one entry");
+				visitor.visit_enum(EnumAccess { variant, value: self.child(value, variant) })
+			}
+			_ => Err(self.unexpected("a string or a table with a single entry")),
+		}
+	}
+
+	fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		self.deserialize_str(visitor)
+	}
+
+	fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		visitor.visit_unit()
+	}
+}
+
+/// Deserializes the keys of tables, which may be parsed into primitives
+struct KeyDeserializer<'de> {
+	key: &'de str,
+	path: String,
+}
+
+impl<'de> KeyDeserializer<'de> {
+	fn string(&self) -> Result<&'de str, SerdeError> {
+		Ok(self.key)
+	}
+}
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer<'de> {
+	type Error = SerdeError;
+
+	fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		visitor.visit_borrowed_str(self.key)
+	}
+
+	fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+		match parse_bool(self.key) {
+			Some(value) => visitor.visit_bool(value),
+			None => Err(<SerdeError as de::Error>::invalid_value(Unexpected::Str(self.key), &"a boolean").at(&self.path)),
+		}
+	}
+
+	deserialize_parse! {
+		deserialize_i8 => visit_i8, deserialize_i16 => visit_i16,
+		deserialize_i32 => visit_i32, deserialize_i64 => visit_i64,
+		deserialize_i128 => visit_i128, deserialize_u8 => visit_u8,
+		deserialize_u16 => visit_u16, deserialize_u32 => visit_u32,
+		deserialize_u64 => visit_u64, deserialize_u128 => visit_u128,
+		deserialize_f32 => visit_f32, deserialize_f64 => visit_f64,
+		deserialize_char => visit_char
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V)
+		-> Result<V::Value, SerdeError> {
+		visitor.visit_newtype_struct(self)
+	}
+
+	fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str],
+	                                     visitor: V) -> Result<V::Value, SerdeError> {
+		visitor.visit_enum(self.key.into_deserializer())
+	}
+
+	forward_to_deserialize_any! {
+		str string bytes byte_buf option unit unit_struct seq tuple tuple_struct map struct
+		identifier ignored_any
+	}
+}
+
+struct SeqAccess<'a, 'de: 'a> {
+	elements: std::iter::Enumerate<slice::Iter<'de, Acon>>,
+	parent: &'a Deserializer<'de>,
+}
+
+impl<'a, 'de> de::SeqAccess<'de> for SeqAccess<'a, 'de> {
+	type Error = SerdeError;
+
+	fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> {
+		match self.elements.next() {
+			Some((index, value)) => {
+				let deserializer = self.parent.child(value, &index.to_string());
+				let path = deserializer.path.clone();
+				seed.deserialize(deserializer).map(Some).map_err(|error| error.at(&path))
+			}
+			None => Ok(None),
+		}
+	}
+
+	fn size_hint(&self) -> Option<usize> {
+		Some(self.elements.len())
+	}
+}
+
+struct MapAccess<'a, 'de: 'a> {
+	entries: map::Iter<'de, String, Acon>,
+	value: Option<(&'de String, &'de Acon)>,
+	parent: &'a Deserializer<'de>,
+}
+
+impl<'a, 'de> de::MapAccess<'de> for MapAccess<'a, 'de> {
+	type Error = SerdeError;
+
+	fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> {
+		match self.entries.next() {
+			Some((key, value)) => {
+				self.value = Some((key, value));
+				let path = child_path(&self.parent.path, key);
+				let deserializer = KeyDeserializer { key, path: path.clone() };
+				seed.deserialize(deserializer).map(Some).map_err(|error| error.at(&path))
+			}
+			None => Ok(None),
+		}
+	}
+
+	fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, SerdeError> {
+		let (key, value) = self.value.take().expect("next_value_seed is called after next_key_seed");
+		let deserializer = self.parent.child(value, key);
+		let path = deserializer.path.clone();
+		seed.deserialize(deserializer).map_err(|error| error.at(&path))
+	}
+
+	fn size_hint(&self) -> Option<usize> {
+		Some(self.entries.len())
+	}
+}
+
+struct EnumAccess<'de> {
+	variant: &'de str,
+	value: Deserializer<'de>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+	type Error = SerdeError;
+	type Variant = Deserializer<'de>;
+
+	fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Deserializer<'de>), SerdeError> {
+		let variant = seed.deserialize(BorrowedStrDeserializer::<SerdeError>::new(self.variant))?;
+		Ok((variant, self.value))
+	}
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer<'de> {
+	type Error = SerdeError;
+
+	fn unit_variant(self) -> Result<(), SerdeError> {
+		de::Deserialize::deserialize(self)
+	}
+
+	fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, SerdeError> {
+		seed.deserialize(self)
+	}
+
+	fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+		de::Deserializer::deserialize_seq(self, visitor)
+	}
+
+	fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V)
+		-> Result<V::Value, SerdeError> {
+		de::Deserializer::deserialize_map(self, visitor)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashMap;
+
+	#[derive(Deserialize, Debug, PartialEq)]
+	struct Server {
+		host: String,
+		port: u16,
+		enabled: bool,
+		weight: Option<f64>,
+		tags: Vec<String>,
+	}
+
+	#[derive(Deserialize, Debug, PartialEq)]
+	enum Mode {
+		Active,
+		Standby(u8),
+		Custom { name: String },
+	}
+
+	#[test]
+	fn structs_and_sequences() {
+		let input = r#"
+			[ servers
+				{
+					host a.example.com
+					port 80
+					enabled yes
+					weight 0.5
+					[ tags
+						web
+						front end
+					]
+				}
+				{
+					host b.example.com
+					port 8080
+					enabled off
+					weight
+					[ tags
+					]
+				}
+			]
+		"#;
+		let servers: HashMap<String, Vec<Server>> = from_str(input).unwrap();
+		let servers = &servers["servers"];
+		assert_eq!(servers[0], Server {
+			host: "a.example.com".to_string(), port: 80, enabled: true, weight: Some(0.5),
+			tags: vec!["web".to_string(), "front end".to_string()],
+		});
+		assert_eq!(servers[1].weight, None);
+		assert!(servers[1].tags.is_empty());
+	}
+
+	#[test]
+	fn enums_and_keys() {
+		let input = "a Active\n{ b\n  Standby 3\n}\n{ c\n  { Custom\n    name x\n  }\n}";
+		let modes: HashMap<char, Mode> = from_str(input).unwrap();
+		assert_eq!(modes[&'a'], Mode::Active);
+		assert_eq!(modes[&'b'], Mode::Standby(3));
+		assert_eq!(modes[&'c'], Mode::Custom { name: "x".to_string() });
+
+		let numbers: HashMap<u8, (u8, String)> = from_str("[ 1\n  2\n  two\n]").unwrap();
+		assert_eq!(numbers[&1], (2, "two".to_string()));
+	}
+
+	#[test]
+	fn borrowed_strings() {
+		let acon = "key value".parse::<Acon>().unwrap();
+		let map: HashMap<&str, &str> = from_acon(&acon).unwrap();
+		assert_eq!(map["key"], "value");
+	}
+
+	#[test]
+	fn errors_carry_paths() {
+		let input = "[ servers\n  {\n    host a\n    port eighty\n  }\n]";
+		let error = from_str::<HashMap<String, Vec<Server>>>(input).unwrap_err();
+		assert_eq!(error.to_string(), "servers.0.port: cannot convert \"eighty\": invalid digit found in string");
+
+		let input = "{ server\n  host a\n}";
+		let error = from_str::<HashMap<String, Server>>(input).unwrap_err();
+		assert_eq!(error.to_string(), "server: missing field `port`");
+
+		let error = from_str::<HashMap<String, u8>>("{ a(46)b\n}").unwrap_err();
+		assert_eq!(error.to_string(), "a(46)b: invalid type: map, expected a string");
+
+		let error = from_str::<HashMap<String, u8>>("{ a\n").unwrap_err();
+		assert!(matches!(error, SerdeError::Parse(_)));
+	}
+}
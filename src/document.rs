@@ -0,0 +1,512 @@
+//! Editing ACON text without losing its formatting
+//!
+//! A document keeps the text it was parsed from, along with the lines that every value spans.
+//! Edits only rewrite the lines of the values they touch, so comments, blank lines,
+//! indentation, `$` and the whitespace in untouched values are all kept.
+
+use std::fmt;
+use std::str::FromStr;
+
+use {escape_value, Acon, AconError, Event, Kind, Path, PathError, PullParser, Segment, Table};
+
+/// A value and the lines it spans, with the values inside of it
+#[derive(Clone, Debug)]
+struct Node {
+	kind: Kind,
+	/// Index of the first line
+	start: usize,
+	/// Index of the line closing a table or array, and whether that line is `}` or `]`
+	/// rather than `$`
+	close: Option<(usize, bool)>,
+	/// An element of an array that is written as a named table or array, which is a table
+	/// with a single entry. The entry shares all lines with the element.
+	wrapper: bool,
+	children: Vec<(Segment, Node)>,
+}
+
+impl Node {
+	fn new(kind: Kind, start: usize) -> Node {
+		Node { kind, start, close: None, wrapper: false, children: Vec::new() }
+	}
+
+	/// Index one past the last line of the value. A `$` also closes the tables and arrays
+	/// around the value, so it is not part of it.
+	fn end(&self) -> usize {
+		match self.close {
+			Some((line, true)) => line + 1,
+			Some((line, false)) => line,
+			None => self.start + 1,
+		}
+	}
+
+	fn child(&self, segment: &Segment) -> Option<&Node> {
+		let segment = match *segment {
+			Segment::Key(ref key) if self.kind == Kind::Array => Segment::Index(key.parse().ok()?),
+			Segment::Index(_) | Segment::Append if self.kind == Kind::Table => Segment::Key(segment.key().into_owned()),
+			ref segment => segment.clone(),
+		};
+		self.children.iter().find(|child| child.0 == segment).map(|child| &child.1)
+	}
+}
+
+/// An ACON document that can be edited while keeping its formatting
+///
+///  ```
+///  use acon::{Acon, Document};
+///  let input = "# Deployment\n{ server\n    host   old.example.com\n\n    port 80\n}\n";
+///  let mut document = input.parse::<Document>().unwrap();
+///  assert_eq!(document.to_string(), input);
+///
+///  document.set("server.host", Acon::String("new.example.com".to_string())).unwrap();
+///  document.insert("server.tls", Acon::String("on".to_string())).unwrap();
+///  document.remove("server.port");
+///  assert_eq!(document.to_string(), "# Deployment\n{ server\n    host new.example.com\n\n    tls on\n}\n");
+///  assert_eq!(document.get("server.tls").unwrap().string(), "on");
+///  ```
+///
+#[derive(Clone, Debug)]
+pub struct Document {
+	text: String,
+	/// The byte offsets at which each line starts, followed by the length of the text
+	lines: Vec<usize>,
+	root: Node,
+	value: Acon,
+}
+
+impl Document {
+	/// The value of the whole document
+	pub fn value(&self) -> &Acon {
+		&self.value
+	}
+
+	/// Retrieve a value by its path
+	pub fn get<P: Into<Path>>(&self, path: P) -> Option<&Acon> {
+		self.value.path(path)
+	}
+
+	/// Set the value at a path as with `Acon::set_path`, returning the value that was there
+	/// before. Only the lines of the value, or of the table or array it is added to, change.
+	/// The document is left as it is if the value can not be written there, such as a string
+	/// under an empty key.
+	pub fn set<P: Into<Path>>(&mut self, path: P, value: Acon) -> Result<Option<Acon>, PathError> {
+		let path = path.into();
+		let mut updated = self.value.clone();
+		let previous = updated.set_path(&path, value)?;
+		self.apply(&path, updated)?;
+		Ok(previous)
+	}
+
+	/// Insert a value at a path as with `Acon::insert_path`, failing if there already is a
+	/// value there or if the value can not be written there
+	pub fn insert<P: Into<Path>>(&mut self, path: P, value: Acon) -> Result<(), PathError> {
+		let path = path.into();
+		let mut updated = self.value.clone();
+		updated.insert_path(&path, value)?;
+		self.apply(&path, updated)
+	}
+
+	/// Remove the value at a path and return it, deleting its lines
+	pub fn remove<P: Into<Path>>(&mut self, path: P) -> Option<Acon> {
+		let path = path.into();
+		let mut updated = self.value.clone();
+		let removed = updated.remove_path(&path)?;
+		let chain = self.chain(&path);
+		let target = chain.len() - 1;
+		self.edit(&path, &updated, |document| {
+			if chain[target - 1].wrapper {
+				// The element is left as an empty table
+				document.replace(&chain, &path, target - 1, &updated)
+			} else {
+				document.splice(chain[target].start, chain[target].end(), "")
+			}
+		}).ok()?;
+		Some(removed)
+	}
+
+	/// The nodes along the path, starting at the root, for as long as they exist
+	fn chain(&self, path: &Path) -> Vec<Node> {
+		let mut chain = vec![self.root.clone()];
+		for segment in path {
+			match chain[chain.len() - 1].child(segment) {
+				Some(child) => {
+					let child = child.clone();
+					chain.push(child);
+				}
+				None => break,
+			}
+		}
+		chain
+	}
+
+	/// Rewrite the text for the updated value
+	fn apply(&mut self, path: &Path, updated: Acon) -> Result<(), PathError> {
+		self.edit(path, &updated, |document| document.rewrite(path, &updated))
+	}
+
+	/// Edit the text, and undo the edit if the text does not parse back into the updated value
+	fn edit<F>(&mut self, path: &Path, updated: &Acon, edit: F) -> Result<(), PathError>
+		where F: FnOnce(&mut Document) -> Result<(), AconError>
+	{
+		let original = self.clone();
+		if edit(self).is_err() || self.value != *updated {
+			*self = original;
+			return Err(PathError::Unwritable(path.to_string()));
+		}
+		Ok(())
+	}
+
+	/// Write the change from the current value to the updated value, which differ at the path
+	fn rewrite(&mut self, path: &Path, updated: &Acon) -> Result<(), AconError> {
+		let chain = self.chain(path);
+		let found = chain.len() - 1;
+		if found == path.len() {
+			let target = if found > 0 && chain[found - 1].wrapper { found - 1 } else { found };
+			self.replace(&chain, path, target, updated)
+		} else if chain[found].wrapper {
+			self.replace(&chain, path, found, updated)
+		} else {
+			self.append(&chain, path, found, updated)
+		}
+	}
+
+	/// Replace the lines of the node at `target` in the chain with the updated value
+	fn replace(&mut self, chain: &[Node], path: &Path, target: usize, updated: &Acon) -> Result<(), AconError> {
+		if target == 0 {
+			let end = self.lines.len() - 1;
+			return self.splice(0, end, &updated.to_string());
+		}
+		let node = &chain[target];
+		let prefix: Path = path.iter().take(target).cloned().collect();
+		let value = updated.path(&prefix).expect("the updated value contains the path");
+		let line = self.line(node.start).to_string();
+		match *value {
+			Acon::String(ref string) if node.kind == Kind::String => {
+				let (content, terminator) = split_terminator(&line);
+				let rewritten = if chain[target - 1].kind == Kind::Array {
+					// An empty element is an empty line, which has no indentation
+					let indentation = self.child_indentation(&chain[target - 1], target == 1);
+					if string.is_empty() { String::new() } else { format!("{}{}", indentation, escape_value(string)) }
+				} else {
+					let indentation = indentation(content);
+					let key = content.split_whitespace().next().unwrap_or("");
+					if string.is_empty() {
+						format!("{}{}", indentation, key)
+					} else {
+						format!("{}{} {}", indentation, key, escape_value(string))
+					}
+				};
+				self.splice(node.start, node.start + 1, &(rewritten + terminator))
+			}
+			_ => {
+				let segment = path.iter().nth(target - 1).cloned().expect("the path reaches the target");
+				let text = serialize(chain[target - 1].kind, &segment, value, indentation(&line));
+				let text = text.replace('\n', self.newline());
+				self.splice(node.start, node.end(), &text)
+			}
+		}
+	}
+
+	/// Add the value at the path to the table or array at `container` in the chain
+	fn append(&mut self, chain: &[Node], path: &Path, container: usize, updated: &Acon) -> Result<(), AconError> {
+		let node = &chain[container];
+		let prefix: Path = path.iter().take(container).cloned().collect();
+		let parent = updated.path(&prefix).expect("the updated value contains the path");
+		let segment = path.iter().nth(container).cloned().expect("the path is longer than the chain");
+		let value = match *parent {
+			Acon::Array(ref array) => array.last(),
+			_ => parent.get_segment(&segment),
+		}.expect("the updated value contains the path");
+
+		let child_indentation = self.child_indentation(node, container == 0);
+		let mut text = String::new();
+		let position = match node.close {
+			Some((line, false)) => {
+				// The $ closes everything that is open, so whatever is open inside of the
+				// table or array has to be closed first
+				let mut open = Vec::new();
+				let mut current = node;
+				while let Some(last) = current.children.last().map(|child| &child.1) {
+					if last.close != Some((line, false)) {
+						break;
+					}
+					if !last.wrapper {
+						open.push(last);
+					}
+					current = last;
+				}
+				for inner in open.iter().rev() {
+					let delimiter = if inner.kind == Kind::Array { "]" } else { "}" };
+					text.push_str(&format!("{}{}\n", indentation(self.line(inner.start)), delimiter));
+				}
+				line
+			}
+			Some((line, true)) => line,
+			None => self.lines.len() - 1,
+		};
+		text.push_str(&serialize(node.kind, &segment, value, &child_indentation));
+		if position == self.lines.len() - 1 && !self.text.is_empty() && !self.text.ends_with('\n') {
+			text.insert(0, '\n');
+		}
+		let text = text.replace('\n', self.newline());
+		self.splice(position, position, &text)
+	}
+
+	/// The indentation of the values in a table or array, which is that of its first value on
+	/// a line that is not empty, or one tab more than the table or array itself
+	fn child_indentation(&self, node: &Node, root: bool) -> String {
+		node.children.iter()
+			.map(|child| self.line(child.1.start))
+			.find(|line| !line.trim().is_empty())
+			.map(|line| indentation(line).to_string())
+			.unwrap_or_else(|| {
+				if root { String::new() } else { indentation(self.line(node.start)).to_string() + "\t" }
+			})
+	}
+
+	fn newline(&self) -> &'static str {
+		if self.text.contains("\r\n") { "\r\n" } else { "\n" }
+	}
+
+	fn line(&self, index: usize) -> &str {
+		&self.text[self.lines[index]..self.lines[index + 1]]
+	}
+
+	/// Replace the lines from `start` up to `end` with the text and parse the result, leaving
+	/// the document as it is if the result does not parse
+	fn splice(&mut self, start: usize, end: usize, text: &str) -> Result<(), AconError> {
+		let mut edited = self.text.clone();
+		edited.replace_range(self.lines[start]..self.lines[end], text);
+		*self = edited.parse()?;
+		Ok(())
+	}
+}
+
+/// The byte offsets at which each line starts, followed by the length of the text
+fn line_starts(text: &str) -> Vec<usize> {
+	let mut starts = vec![0];
+	starts.extend(text.match_indices('\n').map(|(index, _)| index + 1));
+	if starts[starts.len() - 1] != text.len() {
+		starts.push(text.len());
+	}
+	starts
+}
+
+/// Write a value as an entry of a table or an element of an array, indenting every line
+fn serialize(container: Kind, segment: &Segment, value: &Acon, indentation: &str) -> String {
+	let text = if container == Kind::Array {
+		Acon::Array(vec![value.clone()]).to_string()
+	} else {
+		let mut table = Table::new();
+		table.insert(segment.key().into_owned(), value.clone());
+		Acon::Table(table).to_string()
+	};
+	text.split_inclusive('\n')
+		.map(|line| if line == "\n" { line.to_string() } else { format!("{}{}", indentation, line) })
+		.collect()
+}
+
+fn indentation(line: &str) -> &str {
+	let content = split_terminator(line).0;
+	&content[..content.len() - content.trim_start().len()]
+}
+
+fn split_terminator(line: &str) -> (&str, &str) {
+	let content = line.trim_end_matches(['\n', '\r']);
+	(content, &line[content.len()..])
+}
+
+impl FromStr for Document {
+	type Err = AconError;
+
+	fn from_str(text: &str) -> Result<Document, AconError> {
+		let value = text.parse::<Acon>()?;
+		let mut stack = vec![(Segment::Append, Node::new(Kind::Table, 0), false)];
+		for event in PullParser::new(text) {
+			let (span, event) = event?;
+			let line = span.line - 1;
+			match event {
+				Event::TableStart(name) => open(&mut stack, Kind::Table, name.into_owned(), line),
+				Event::ArrayStart(name) => open(&mut stack, Kind::Array, name.into_owned(), line),
+				Event::Entry(key, _) => {
+					let top = &mut stack.last_mut().expect("the root is never closed").1;
+					top.children.push((Segment::Key(key.into_owned()), Node::new(Kind::String, line)));
+				}
+				Event::ArrayItem(_) => {
+					let top = &mut stack.last_mut().expect("the root is never closed").1;
+					let index = top.children.len();
+					top.children.push((Segment::Index(index), Node::new(Kind::String, line)));
+				}
+				Event::End => close(&mut stack, line, true),
+				Event::CloseAll => {
+					while stack.len() > 1 {
+						close(&mut stack, line, false);
+					}
+				}
+				Event::Comment(_) => {}
+			}
+		}
+		let root = stack.pop().expect("the root is never closed").1;
+		return Ok(Document { text: text.to_string(), lines: line_starts(text), root, value });
+
+		// A named table or array in an array is wrapped in a table once it is closed
+		fn open(stack: &mut Vec<(Segment, Node, bool)>, kind: Kind, name: String, line: usize) {
+			let wrapped = stack[stack.len() - 1].1.kind == Kind::Array && !name.is_empty();
+			stack.push((Segment::Key(name), Node::new(kind, line), wrapped));
+		}
+
+		fn close(stack: &mut Vec<(Segment, Node, bool)>, line: usize, explicit: bool) {
+			let (segment, mut node, wrapped) = stack.pop().expect("the parser checks the delimiters");
+			node.close = Some((line, explicit));
+			let parent = &mut stack.last_mut().expect("the parser checks the delimiters").1;
+			let index = Segment::Index(parent.children.len());
+			if wrapped {
+				let mut wrapper = Node::new(Kind::Table, node.start);
+				wrapper.close = node.close;
+				wrapper.wrapper = true;
+				wrapper.children.push((segment, node));
+				parent.children.push((index, wrapper));
+			} else if parent.kind == Kind::Array {
+				parent.children.push((index, node));
+			} else {
+				parent.children.push((segment, node));
+			}
+		}
+	}
+}
+
+impl fmt::Display for Document {
+	/// Write the document, which is the parsed text with the edits applied
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.text)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn string(value: &str) -> Acon {
+		Acon::String(value.to_string())
+	}
+
+	/// Check that the document holds the same value as a tree with the same edits
+	fn check(document: &Document, expected: &Acon) {
+		assert_eq!(document.value(), expected);
+		assert_eq!(&document.to_string().parse::<Acon>().unwrap(), expected);
+	}
+
+	#[test]
+	fn lossless() {
+		for input in &["", "\n\n", "key   value  \r\n  # comment\r\n", "{ a\n\t[ b\n\n\t\tx\n$ rest\n  key (32)v", "[ a\n]"] {
+			assert_eq!(input.parse::<Document>().unwrap().to_string(), *input);
+		}
+	}
+
+	#[test]
+	fn edit_entries() {
+		let input = "# header\n{ table\n  key   old   value\n  other x\n}\n\nlast thing\n";
+		let mut document = input.parse::<Document>().unwrap();
+		let mut expected = input.parse::<Acon>().unwrap();
+
+		assert_eq!(document.set("table.key", string("new")).unwrap(), Some(string("old value")));
+		expected.set_path("table.key", string("new")).unwrap();
+		assert_eq!(document.to_string(), "# header\n{ table\n  key new\n  other x\n}\n\nlast thing\n");
+
+		document.set("table.sub.deep", string("a b")).unwrap();
+		expected.set_path("table.sub.deep", string("a b")).unwrap();
+		assert_eq!(document.to_string(), "# header\n{ table\n  key new\n  other x\n  { sub\n  \tdeep a b\n  }\n}\n\nlast thing\n");
+
+		document.set("table.+", string("plus")).unwrap();
+		expected.set_path("table.+", string("plus")).unwrap();
+		document.set("table.+", string("key")).unwrap();
+		expected.set_path("table.+", string("key")).unwrap();
+		assert_eq!(document.get("table.+"), Some(&string("key")));
+		check(&document, &expected);
+
+		assert_eq!(document.insert("table.key", string("x")), Err(PathError::Exists("table.key".to_string())));
+		assert_eq!(document.remove("table"), expected.remove_path("table"));
+		assert_eq!(document.to_string(), "# header\n\nlast thing\n");
+		check(&document, &expected);
+	}
+
+	#[test]
+	fn edit_arrays() {
+		let input = "[ list\n    one\n\n    { named\n        x y\n    }\n]\n";
+		let mut document = input.parse::<Document>().unwrap();
+		let mut expected = input.parse::<Acon>().unwrap();
+
+		document.set("list.+", string("four")).unwrap();
+		expected.set_path("list.+", string("four")).unwrap();
+		document.set("list.1", string("two")).unwrap();
+		expected.set_path("list.1", string("two")).unwrap();
+		assert_eq!(document.to_string(), "[ list\n    one\n    two\n    { named\n        x y\n    }\n    four\n]\n");
+
+		document.set("list.2.named.z", string("w")).unwrap();
+		expected.set_path("list.2.named.z", string("w")).unwrap();
+		document.set("list.2.extra", string("v")).unwrap();
+		expected.set_path("list.2.extra", string("v")).unwrap();
+		check(&document, &expected);
+		assert!(document.to_string().starts_with("[ list\n    one\n    two\n    {\n"));
+
+		document.remove("list.0");
+		expected.remove_path("list.0");
+		check(&document, &expected);
+	}
+
+	#[test]
+	fn edit_inside_dollar() {
+		let input = "{ a\n\t[ b\n\t\tx\n\t\t{ c\n\t\t\tkey value\n$\nafter it\n";
+		let mut document = input.parse::<Document>().unwrap();
+		let mut expected = input.parse::<Acon>().unwrap();
+
+		document.set("a.new", string("entry")).unwrap();
+		expected.set_path("a.new", string("entry")).unwrap();
+		assert_eq!(document.to_string(), "{ a\n\t[ b\n\t\tx\n\t\t{ c\n\t\t\tkey value\n\t\t}\n\t]\n\tnew entry\n$\nafter it\n");
+		check(&document, &expected);
+
+		let mut document = input.parse::<Document>().unwrap();
+		let mut expected = input.parse::<Acon>().unwrap();
+		document.remove("a.b");
+		expected.remove_path("a.b");
+		assert_eq!(document.to_string(), "{ a\n$\nafter it\n");
+		check(&document, &expected);
+	}
+
+	#[test]
+	fn edit_without_trailing_newline() {
+		let mut document = "a b\r\nc d".parse::<Document>().unwrap();
+		document.set("e", Acon::Array(vec![string("f")])).unwrap();
+		assert_eq!(document.to_string(), "a b\r\nc d\r\n[ e\r\n\tf\r\n]\r\n");
+		document.set("e.0", Acon::Array(Vec::new())).unwrap();
+		assert_eq!(document.to_string(), "a b\r\nc d\r\n[ e\r\n\t[\r\n\t]\r\n]\r\n");
+		document.set("", Acon::Table(Table::new())).unwrap();
+		assert_eq!(document.get("").unwrap(), &Acon::Table(Table::new()));
+	}
+
+	#[test]
+	fn unwritable_values() {
+		let input = "{ t
+	key value
+}
+[ a
+]
+";
+		let mut document = input.parse::<Document>().unwrap();
+		assert_eq!(document.set("t.", string("v")), Err(PathError::Unwritable("t.".to_string())));
+		assert_eq!(document.insert("", string("v")), Err(PathError::Unwritable("".to_string())));
+		let mut table = Table::new();
+		table.insert(String::new(), string("v"));
+		assert_eq!(document.set("a.+", Acon::Table(table)), Err(PathError::Unwritable("a.+.".to_string())));
+		assert_eq!(document.to_string(), input);
+		assert_eq!(document.value(), &input.parse::<Acon>().unwrap());
+		document.set("t.", Acon::Array(Vec::new())).unwrap();
+		assert_eq!(document.to_string(), "{ t
+	key value
+	[
+	]
+}
+[ a
+]
+");
+	}
+}
@@ -0,0 +1,521 @@
+//! Errors that come about during parsing, and their locations in the input
+//!
+//! Also contains the errors for reading and writing, for accessing a value as the wrong kind
+//! and for modifying a value by its path.
+
+use std::fmt;
+use std::io;
+
+/// A location in the input
+///
+/// Lines and columns start at 1, and columns are counted in characters. The byte range
+/// `start..end` covers the offending word in the input.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct Span {
+	/// Line number, starting at 1
+	pub line: usize,
+	/// Column of the first character, starting at 1
+	pub column: usize,
+	/// Byte offset of the first byte
+	pub start: usize,
+	/// Byte offset one past the last byte
+	pub end: usize,
+}
+
+impl Span {
+	/// Create the span of a word that is a slice of a line. The line itself starts at byte
+	/// `offset` of the input.
+	pub(crate) fn of(word: &str, line: &str, number: usize, offset: usize) -> Span {
+		let start = word.as_ptr() as usize - line.as_ptr() as usize;
+		Span {
+			line: number,
+			column: line[..start].chars().count() + 1,
+			start: offset + start,
+			end: offset + start + word.len(),
+		}
+	}
+}
+
+impl fmt::Display for Span {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}", self.line, self.column)
+	}
+}
+
+/// Errors that come about during parsing
+#[derive(PartialEq, Clone, Debug)]
+pub enum AconError {
+	/// Indicates that there are too many closing delimiters compared to opening
+	/// delimiters. Contains the span of the closing delimiter.
+	ExcessiveClosingDelimiter(Span),
+	/// Acon::String is the top of the stack. This indicates an interal error
+	InternalStringTop(Span),
+	/// The stack top is missing, indicating that something popped the top
+	MissingStackTop(Span),
+	/// There is more than one top node after parsing the input. Unterminated tables.
+	/// Contains the span of the opening delimiter of the innermost unterminated table.
+	MultipleTopNodes(Span),
+	/// If the top node of the stack is an array, this indicates that there's an
+	/// unterminated array. Contains the span of its opening delimiter.
+	TopNodeIsArray(Span),
+	/// The key is already present in the parent table. Contains the span of the key,
+	/// or of the closing delimiter and the opening delimiter of the closed table or array.
+	OverwritingKey(Span, Option<Span>),
+	/// Got a } but expected a ]. Contains the spans of the closing and opening delimiters.
+	WrongClosingDelimiterExpectedArray(Span, Span),
+	/// Got a ] but expected a }. Contains the spans of the closing and opening delimiters.
+	WrongClosingDelimiterExpectedTable(Span, Span),
+	/// The input is longer than the parser options allow. Contains the span of the line that
+	/// exceeds the limit, and the limit in bytes.
+	ExcessiveInput(Span, usize),
+	/// Tables and arrays are nested deeper than the parser options allow. Contains the span
+	/// of the opening delimiter, and the limit.
+	ExcessiveDepth(Span, usize),
+	/// A table has more keys than the parser options allow. Contains the span of the key or
+	/// closing delimiter that adds the key, and the limit.
+	ExcessiveKeys(Span, usize),
+	/// An array has more elements than the parser options allow. Contains the span of the
+	/// element or closing delimiter that adds the element, and the limit.
+	ExcessiveArrayLength(Span, usize),
+	/// A value is longer than the parser options allow. Contains the span of the value, and
+	/// the limit in bytes.
+	ExcessiveValueLength(Span, usize),
+	/// A key, or the name of a table or array, is longer than the parser options allow.
+	/// Contains the span of the key or name, and the limit in bytes.
+	ExcessiveKeyLength(Span, usize),
+}
+
+impl AconError {
+	/// Returns a human-friendly explanation of the parse error.
+	///
+	///  ```
+	///  use acon::Acon;
+	///  let error = "key value\nkey value".parse::<Acon>().unwrap_err();
+	///  assert_eq!(error.reason(), "On line 2, the key is already present in the table.");
+	///  ```
+	///
+	pub fn reason(&self) -> String {
+		use AconError::*;
+		match *self {
+			ExcessiveClosingDelimiter(span) => {
+				format!("On line {}, there's a closing delimiter that has no matching opening delimiter. Note that
+all delimiters must be the first word on a line to count as such. The only delimiters are {}, {}, [, ], and $.",
+				span.line, "{", "}")
+			}
+			InternalStringTop(span) => {
+				format!("On line {}, there's a string on the top of the internal parse stack. This is impossible unless there is a
+bug in the parser. Please report this along with the input to the repository maintainer of ACON.", span.line)
+			}
+			MissingStackTop(span) => {
+				format!("On line {}, the top of the stack is missing. This indicates an internal error, as it's never supposed to
+happen. Please contact the maintainer of the ACON repository.", span.line)
+			}
+			MultipleTopNodes(span) => {
+				format!("The table opened on line {} is unterminated, you can append '$' to the input or try terminating it by finding a flaw in the input.", span.line)
+			}
+			TopNodeIsArray(span) => {
+				format!("The top of the stack is an array. This indicates that the array opened on line {} is unterminated all the way
+until the end of the input. Try appending a ']' to the input to see if this solves the issue.", span.line)
+			}
+			OverwritingKey(span, _) => {
+				format!("On line {}, the key is already present in the table.", span.line)
+			}
+			WrongClosingDelimiterExpectedArray(span, _) => {
+				format!("On line {}, the closing delimiter did not match the array closing delimiter ]. Make sure all delimiters
+match up in the input. Some editors can help you by jumping from/to each delimiter.", span.line)
+			}
+			WrongClosingDelimiterExpectedTable(span, _) => {
+				format!("On line {}, the closing delimiter did not match the table closing delimiter {}. Make sure all delimiters
+match up in the input. Some editors can help you by jumping from/to each delimiter.", span.line, "}")
+			}
+			ExcessiveInput(span, limit) => {
+				format!("On line {}, the input exceeds the limit of {} bytes.", span.line, limit)
+			}
+			ExcessiveDepth(span, limit) => {
+				format!("On line {}, the tables and arrays are nested deeper than the limit of {}.", span.line, limit)
+			}
+			ExcessiveKeys(span, limit) => {
+				format!("On line {}, the table exceeds the limit of {} keys.", span.line, limit)
+			}
+			ExcessiveArrayLength(span, limit) => {
+				format!("On line {}, the array exceeds the limit of {} elements.", span.line, limit)
+			}
+			ExcessiveValueLength(span, limit) => {
+				format!("On line {}, the value exceeds the limit of {} bytes.", span.line, limit)
+			}
+			ExcessiveKeyLength(span, limit) => {
+				format!("On line {}, the key exceeds the limit of {} bytes.", span.line, limit)
+			}
+		}
+	}
+
+	/// The location in the input where the error was detected
+	pub fn span(&self) -> Span {
+		use AconError::*;
+		match *self {
+			ExcessiveClosingDelimiter(span) | InternalStringTop(span) | MissingStackTop(span)
+			| MultipleTopNodes(span) | TopNodeIsArray(span) | OverwritingKey(span, _)
+			| WrongClosingDelimiterExpectedArray(span, _)
+			| WrongClosingDelimiterExpectedTable(span, _) | ExcessiveInput(span, _)
+			| ExcessiveDepth(span, _) | ExcessiveKeys(span, _) | ExcessiveArrayLength(span, _)
+			| ExcessiveValueLength(span, _) | ExcessiveKeyLength(span, _) => span,
+		}
+	}
+
+	/// The location of the opening delimiter of the table or array involved in the error,
+	/// if it is not the location returned by `span`
+	pub fn opened(&self) -> Option<Span> {
+		use AconError::*;
+		match *self {
+			OverwritingKey(_, opened) => opened,
+			WrongClosingDelimiterExpectedArray(_, opened)
+			| WrongClosingDelimiterExpectedTable(_, opened) => Some(opened),
+			_ => None,
+		}
+	}
+
+	/// Render the error against the input it came from, in the style of compiler
+	/// diagnostics. The offending word is underlined with carets, and the opening
+	/// delimiter involved in the error is pointed out.
+	///
+	///  ```
+	///  use acon::Acon;
+	///  let input = "{ table\n  key value\n]\n";
+	///  let error = input.parse::<Acon>().unwrap_err();
+	///  assert_eq!(error.render(input), "\
+	///  error: expected } to close the table
+	///   --> 3:1
+	///    |
+	///  1 | { table
+	///    | - the table is opened here
+	///    |
+	///  3 | ]
+	///    | ^ expected }
+	///  ");
+	///  ```
+	///
+	pub fn render(&self, source: &str) -> String {
+		use AconError::*;
+		let (title, label, note) = match *self {
+			ExcessiveClosingDelimiter(_) => ("closing delimiter without an opening delimiter", "unmatched delimiter", ""),
+			InternalStringTop(_) => ("string on the top of the parse stack", "internal error", ""),
+			MissingStackTop(_) => ("missing top of the parse stack", "internal error", ""),
+			MultipleTopNodes(_) => ("unterminated table", "this table is never closed", ""),
+			TopNodeIsArray(_) => ("unterminated array", "this array is never closed", ""),
+			OverwritingKey(..) => ("the key is already present in the table", "duplicate key", "the duplicate is opened here"),
+			WrongClosingDelimiterExpectedArray(..) => ("expected ] to close the array", "expected ]", "the array is opened here"),
+			WrongClosingDelimiterExpectedTable(..) => ("expected } to close the table", "expected }", "the table is opened here"),
+			ExcessiveInput(..) => ("the input is too long", "the limit is exceeded here", ""),
+			ExcessiveDepth(..) => ("the nesting is too deep", "too deep", ""),
+			ExcessiveKeys(..) => ("the table has too many keys", "one key too many", ""),
+			ExcessiveArrayLength(..) => ("the array has too many elements", "one element too many", ""),
+			ExcessiveValueLength(..) => ("the value is too long", "too long", ""),
+			ExcessiveKeyLength(..) => ("the key is too long", "too long", ""),
+		};
+		let span = self.span();
+		let opened = self.opened();
+		let width = opened.map_or(span.line, |opened| opened.line.max(span.line)).to_string().len();
+		let gutter = " ".repeat(width);
+
+		let mut rendered = format!("error: {}\n{}--> {}\n{} |\n", title, gutter, span, gutter);
+		if let Some(opened) = opened {
+			snippet(&mut rendered, source, opened, '-', note, width);
+			rendered.push_str(&format!("{} |\n", gutter));
+		}
+		snippet(&mut rendered, source, span, '^', label, width);
+		return rendered;
+
+		fn snippet(rendered: &mut String, source: &str, span: Span, marker: char, label: &str, width: usize) {
+			let line = source.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+			rendered.push_str(&format!("{:>width$} | {}\n", span.line, line, width = width));
+			// Mirror tabs so that the markers line up with the word regardless of tab width
+			let padding: String = line.chars().take(span.column.saturating_sub(1))
+				.map(|character| if character == '\t' { '\t' } else { ' ' })
+				.collect();
+			let length = source.get(span.start..span.end).map_or(1, |word| word.chars().count().max(1));
+			let markers: String = std::iter::repeat_n(marker, length).collect();
+			rendered.push_str(&format!("{} | {}{} {}\n", " ".repeat(width), padding, markers, label));
+		}
+	}
+}
+
+impl fmt::Display for AconError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.reason())
+	}
+}
+
+impl std::error::Error for AconError {}
+
+/// Errors that come about when reading and parsing input from a reader
+#[derive(Debug)]
+pub enum ReadError {
+	/// The reader failed
+	Io(io::Error),
+	/// The input is not valid UTF-8. Contains the span of the first invalid byte.
+	InvalidUtf8(Span),
+	/// The input could not be parsed as ACON
+	Parse(AconError),
+}
+
+impl fmt::Display for ReadError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ReadError::Io(ref error) => write!(f, "Could not read the input: {}", error),
+			ReadError::InvalidUtf8(span) => write!(f, "On line {}, the input is not valid UTF-8.", span.line),
+			ReadError::Parse(ref error) => error.fmt(f),
+		}
+	}
+}
+
+impl std::error::Error for ReadError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match *self {
+			ReadError::Io(ref error) => Some(error),
+			ReadError::InvalidUtf8(_) => None,
+			ReadError::Parse(ref error) => Some(error),
+		}
+	}
+}
+
+impl From<io::Error> for ReadError {
+	fn from(error: io::Error) -> ReadError {
+		ReadError::Io(error)
+	}
+}
+
+impl From<AconError> for ReadError {
+	fn from(error: AconError) -> ReadError {
+		ReadError::Parse(error)
+	}
+}
+
+/// Errors that come about when writing with `AconWriter`
+#[derive(Debug)]
+pub enum WriteError {
+	/// The underlying writer failed
+	Io(io::Error),
+	/// An entry was written directly inside of an array
+	EntryInArray,
+	/// An item was written outside of an array
+	ItemOutsideArray,
+	/// An entry was written with an empty key, which can only name a table or array
+	EmptyKey,
+	/// A table or array was closed while none was open
+	ExcessiveEnd,
+	/// The writer was finished while tables or arrays were open. Contains their number.
+	Unclosed(usize),
+}
+
+impl fmt::Display for WriteError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			WriteError::Io(ref error) => write!(f, "Could not write the output: {}", error),
+			WriteError::EntryInArray => f.write_str("An array has items, not entries."),
+			WriteError::ItemOutsideArray => f.write_str("A table has entries, not items."),
+			WriteError::EmptyKey => f.write_str("An entry needs a key that is not empty."),
+			WriteError::ExcessiveEnd => f.write_str("There is no open table or array to close."),
+			WriteError::Unclosed(open) => write!(f, "There are {} tables or arrays left open.", open),
+		}
+	}
+}
+
+impl std::error::Error for WriteError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match *self {
+			WriteError::Io(ref error) => Some(error),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for WriteError {
+	fn from(error: io::Error) -> WriteError {
+		WriteError::Io(error)
+	}
+}
+
+/// The kinds of values in ACON
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Kind {
+	/// An array of values
+	Array,
+	/// A string
+	String,
+	/// A table of keys mapped to values
+	Table,
+}
+
+impl fmt::Display for Kind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			Kind::Array => "an array",
+			Kind::String => "a string",
+			Kind::Table => "a table",
+		})
+	}
+}
+
+/// A value is of another kind than the one that was asked for
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct KindError {
+	/// The kind that was asked for
+	pub expected: Kind,
+	/// The kind of the value
+	pub actual: Kind,
+}
+
+impl fmt::Display for KindError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "expected {}, found {}", self.expected, self.actual)
+	}
+}
+
+impl std::error::Error for KindError {}
+
+/// Errors that come about when modifying a value by its path
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum PathError {
+	/// The path goes through a string, which has no entries. Contains the path of the string.
+	TraversesString(String),
+	/// The path goes through an array with a segment that is neither the index of an element
+	/// nor `+`. Contains the path up to and including the segment.
+	InvalidIndex(String),
+	/// There already is a value at the path
+	Exists(String),
+	/// The value can not be written as ACON at the path, as with a string under an empty key
+	Unwritable(String),
+}
+
+impl fmt::Display for PathError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			PathError::TraversesString(ref path) => write!(f, "{}: a string has no entries", path),
+			PathError::InvalidIndex(ref path) => write!(f, "{}: not an index of the array, nor +", path),
+			PathError::Exists(ref path) => write!(f, "{}: there already is a value", path),
+			PathError::Unwritable(ref path) => write!(f, "{}: the value can not be written there", path),
+		}
+	}
+}
+
+impl std::error::Error for PathError {}
+
+/// Errors that come about when serializing or deserializing with serde
+#[cfg(feature = "serde")]
+#[derive(PartialEq, Clone, Debug)]
+pub enum SerdeError {
+	/// The input could not be parsed as ACON
+	Parse(AconError),
+	/// The data does not fit the type. Contains the dot-separated path of the offending
+	/// value, which is empty for the root.
+	Message {
+		/// Path of the offending value
+		path: String,
+		/// Explanation of the error
+		message: String,
+	},
+}
+
+#[cfg(feature = "serde")]
+impl SerdeError {
+	/// Set the path of the error unless it is already known
+	pub(crate) fn at(mut self, at: &str) -> SerdeError {
+		if let SerdeError::Message { ref mut path, .. } = self {
+			if path.is_empty() {
+				*path = at.to_string();
+			}
+		}
+		self
+	}
+
+	/// Prepend a segment to the path of the error
+	pub(crate) fn within(self, segment: &str) -> SerdeError {
+		match self {
+			SerdeError::Message { path, message } => SerdeError::Message {
+				path: if path.is_empty() { segment.to_string() } else { format!("{}.{}", segment, path) },
+				message,
+			},
+			other => other,
+		}
+	}
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for SerdeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			SerdeError::Parse(ref error) => error.fmt(f),
+			SerdeError::Message { ref path, ref message } if path.is_empty() => f.write_str(message),
+			SerdeError::Message { ref path, ref message } => write!(f, "{}: {}", path, message),
+		}
+	}
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for SerdeError {}
+
+#[cfg(feature = "serde")]
+impl ::serde::de::Error for SerdeError {
+	fn custom<T: fmt::Display>(message: T) -> SerdeError {
+		SerdeError::Message { path: String::new(), message: message.to_string() }
+	}
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::ser::Error for SerdeError {
+	fn custom<T: fmt::Display>(message: T) -> SerdeError {
+		SerdeError::Message { path: String::new(), message: message.to_string() }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use Acon;
+
+	#[test]
+	fn spans() {
+		let input = "key value\n\t{ table\n\t\tkey value\n\t\tkey value\n";
+		let error = input.parse::<Acon>().unwrap_err();
+		assert_eq!(error, AconError::OverwritingKey(Span { line: 4, column: 3, start: 33, end: 36 }, None));
+		assert_eq!(&input[error.span().start..error.span().end], "key");
+	}
+
+	#[test]
+	fn unterminated_spans() {
+		let input = "{ a\n\t[ b\n\t]\n";
+		assert_eq!(input.parse::<Acon>(), Err(AconError::MultipleTopNodes(Span { line: 1, column: 1, start: 0, end: 1 })));
+		let input = "{ a\n}\r\n\t[ b\n";
+		assert_eq!(input.parse::<Acon>(), Err(AconError::TopNodeIsArray(Span { line: 3, column: 2, start: 8, end: 9 })));
+	}
+
+	#[test]
+	fn render_duplicate_close() {
+		let input = "{ key\n}\n\t[ key\n\t]\n";
+		let error = input.parse::<Acon>().unwrap_err();
+		assert_eq!(error.opened(), Some(Span { line: 3, column: 2, start: 9, end: 10 }));
+		assert_eq!(error.render(input), "\
+error: the key is already present in the table
+ --> 4:2
+  |
+3 | \t[ key
+  | \t- the duplicate is opened here
+  |
+4 | \t]
+  | \t^ duplicate key
+");
+	}
+
+	#[test]
+	fn render_key() {
+		let input = "\n\n\n\n\n\n\n\n\nkey value\nkey value";
+		let error = input.parse::<Acon>().unwrap_err();
+		assert_eq!(error.render(input), "\
+error: the key is already present in the table
+  --> 11:1
+   |
+11 | key value
+   | ^^^ duplicate key
+");
+	}
+}
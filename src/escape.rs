@@ -0,0 +1,160 @@
+//! Escape codes used by ACON
+//!
+//! A character is escaped by writing its decimal code point in parentheses, so a space
+//! becomes `(32)`, a dot becomes `(46)` and a new-line becomes `(10)`. Parentheses that do
+//! not enclose a valid code point are left as they are.
+
+use std::borrow::Cow;
+
+/// Words that carry meaning when they are the first word on a line.
+const DELIMITERS: &[char] = &['#', '$', '[', ']', '{', '}'];
+
+/// Escape a string so that it can be written as a single word.
+///
+/// This is used for keys and the names of tables and arrays. All whitespace and
+/// dots are escaped, so the result can be used in a dot-separated path.
+///
+///  ```
+///  assert_eq!(acon::escape("a key.with dots"), "a(32)key(46)with(32)dots");
+///  ```
+///
+pub fn escape(string: &str) -> String {
+	escape_with(string, true)
+}
+
+/// Escape a string so that it can be written as a value.
+///
+/// Values may contain single spaces between words, so only whitespace that
+/// would otherwise be trimmed or collapsed by the parser is escaped.
+///
+///  ```
+///  assert_eq!(acon::escape_value("two words\nand a line"), "two words(10)and a line");
+///  ```
+///
+pub fn escape_value(string: &str) -> String {
+	escape_with(string, false)
+}
+
+/// Replace all escape codes in a string by the characters they denote.
+///
+///  ```
+///  assert_eq!(acon::unescape("a(32)key(46)with(32)dots"), "a key.with dots");
+///  assert_eq!(acon::unescape("f(x) (y)"), "f(x) (y)");
+///  ```
+///
+pub fn unescape(string: &str) -> String {
+	let mut unescaped = String::with_capacity(string.len());
+	let mut rest = string;
+	while let Some(start) = rest.find('(') {
+		unescaped.push_str(&rest[..start]);
+		rest = &rest[start..];
+		match decode(rest) {
+			Some((character, length)) => {
+				unescaped.push(character);
+				rest = &rest[length..];
+			}
+			None => {
+				unescaped.push('(');
+				rest = &rest[1..];
+			}
+		}
+	}
+	unescaped.push_str(rest);
+	unescaped
+}
+
+/// Unescape a string, borrowing it if it contains no escape codes
+pub(crate) fn unescape_cow(string: &str) -> Cow<'_, str> {
+	if string.contains('(') {
+		Cow::Owned(unescape(string))
+	} else {
+		Cow::Borrowed(string)
+	}
+}
+
+/// Decode the escape code at the start of the string, returning the character and the
+/// length of the code in bytes.
+fn decode(string: &str) -> Option<(char, usize)> {
+	if !string.starts_with('(') {
+		return None;
+	}
+	let digits = string[1..].bytes().take_while(u8::is_ascii_digit).count();
+	if digits == 0 || string.as_bytes().get(1 + digits) != Some(&b')') {
+		return None;
+	}
+	string[1..1 + digits].parse::<u32>().ok()
+		.and_then(std::char::from_u32)
+		.map(|character| (character, digits + 2))
+}
+
+fn escape_with(string: &str, word: bool) -> String {
+	let mut escaped = String::with_capacity(string.len());
+	let mut previous = None;
+	for (index, character) in string.char_indices() {
+		let rest = &string[index + character.len_utf8()..];
+		let needs_escape = match character {
+			'(' => decode(&string[index..]).is_some(),
+			'.' => word,
+			' ' if !word => index == 0 || rest.is_empty() || previous == Some(' '),
+			character if character.is_whitespace() => true,
+			character if index == 0 && DELIMITERS.contains(&character) => {
+				rest.chars().next().is_none_or(char::is_whitespace)
+			}
+			_ => false,
+		};
+		if needs_escape {
+			escaped.push_str(&format!("({})", character as u32));
+		} else {
+			escaped.push(character);
+		}
+		previous = Some(character);
+	}
+	escaped
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn unescape_codes() {
+		assert_eq!(unescape("value(10)with(10)new(10)lines"), "value\nwith\nnew\nlines");
+		assert_eq!(unescape("(35)"), "#");
+		assert_eq!(unescape("(128512)"), "\u{1F600}");
+	}
+
+	#[test]
+	fn unescape_leaves_invalid_codes() {
+		assert_eq!(unescape("CreateNewDoc()"), "CreateNewDoc()");
+		assert_eq!(unescape("(abc) (12 (55296) (99999999999) ("), "(abc) (12 (55296) (99999999999) (");
+	}
+
+	#[test]
+	fn escape_words() {
+		assert_eq!(escape("key with_space.and_dot"), "key(32)with_space(46)and_dot");
+		assert_eq!(escape("#"), "(35)");
+		assert_eq!(escape("{ table"), "(123)(32)table");
+		assert_eq!(escape("#hashtag"), "#hashtag");
+		assert_eq!(escape("f(32)"), "f(40)32)");
+		assert_eq!(escape("f()"), "f()");
+	}
+
+	#[test]
+	fn escape_values() {
+		assert_eq!(escape_value("a b.c"), "a b.c");
+		assert_eq!(escape_value(" a  b "), "(32)a (32)b(32)");
+		assert_eq!(escape_value("a\tb\r\n"), "a(9)b(13)(10)");
+		assert_eq!(escape_value("$ close"), "(36) close");
+		assert_eq!(escape_value("] x"), "(93) x");
+	}
+
+	#[test]
+	fn round_trip() {
+		let strings = ["", " ", "a  b", "(10)", "((10))", "\n\n", "$", "x.y z", "\u{85}a\u{a0}"];
+		for string in &strings {
+			assert_eq!(unescape(&escape(string)), *string);
+			assert_eq!(unescape(&escape_value(string)), *string);
+			assert_eq!(escape(string).split_whitespace().count(), if string.is_empty() { 0 } else { 1 });
+		}
+	}
+}
@@ -0,0 +1,305 @@
+//! Parsing ACON into a sequence of events
+//!
+//! Every line of the input produces at most one event. The pull parser checks that the
+//! delimiters match up, but leaves it to the consumer to decide what to do with the entries,
+//! so duplicate keys are only reported when building a tree.
+
+use std::borrow::Cow;
+use std::str::{self, SplitInclusive, Utf8Error};
+
+use escape::unescape_cow;
+use {unescape, AconError, Kind, ParserOptions, Span};
+
+/// Something found in the input
+///
+/// Keys, values and names are unescaped. They borrow from the input unless unescaping or
+/// collapsing whitespace changed them.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Event<'a> {
+	/// A table is opened, with its name, which is empty if the table has no name
+	TableStart(Cow<'a, str>),
+	/// An array is opened, with its name, which is empty if the array has no name
+	ArrayStart(Cow<'a, str>),
+	/// A key and its value in a table
+	Entry(Cow<'a, str>, Cow<'a, str>),
+	/// An element of an array
+	ArrayItem(Cow<'a, str>),
+	/// The innermost table or array is closed
+	End,
+	/// All tables and arrays are closed with `$`
+	CloseAll,
+	/// A comment, with the text after the `#`
+	Comment(Cow<'a, str>),
+}
+
+impl<'a> Event<'a> {
+	/// Copy any text that is borrowed from the input
+	pub fn into_owned(self) -> Event<'static> {
+		fn owned(text: Cow<str>) -> Cow<'static, str> {
+			Cow::Owned(text.into_owned())
+		}
+		match self {
+			Event::TableStart(name) => Event::TableStart(owned(name)),
+			Event::ArrayStart(name) => Event::ArrayStart(owned(name)),
+			Event::Entry(key, value) => Event::Entry(owned(key), owned(value)),
+			Event::ArrayItem(value) => Event::ArrayItem(owned(value)),
+			Event::End => Event::End,
+			Event::CloseAll => Event::CloseAll,
+			Event::Comment(text) => Event::Comment(owned(text)),
+		}
+	}
+}
+
+/// The state shared by everything that parses line by line
+#[derive(Default)]
+pub(crate) struct State {
+	open: Vec<(Kind, Span)>,
+	line: usize,
+	offset: usize,
+	options: ParserOptions,
+}
+
+impl State {
+	pub(crate) fn new(options: ParserOptions) -> State {
+		State { options, ..State::default() }
+	}
+
+	/// Parse a line, including its line terminator if it has one
+	pub(crate) fn line<'a>(&mut self, raw: &'a str) -> Result<Option<(Span, Event<'a>)>, AconError> {
+		if self.offset + raw.len() > self.options.max_total_bytes {
+			return Err(self.excessive_input(raw.as_bytes()));
+		}
+		self.line += 1;
+		let line_offset = self.offset;
+		self.offset += raw.len();
+		let line = raw.strip_suffix('\n').unwrap_or(raw);
+		let line = line.strip_suffix('\r').unwrap_or(line);
+		let number = self.line;
+		let span = |word: &str| Span::of(word, line, number, line_offset);
+
+		let first = match line.split_whitespace().next() {
+			Some(first) => first,
+			None => {
+				return Ok(match self.open.last() {
+					Some(&(Kind::Array, _)) => Some((span(line), Event::ArrayItem(Cow::Borrowed("")))),
+					_ => None,
+				});
+			}
+		};
+		let at = span(first);
+		let rest = &line[at.start - line_offset + first.len()..];
+		let event = match first {
+			"{" | "[" => {
+				let kind = if first == "{" { Kind::Table } else { Kind::Array };
+				let depth = self.open.len();
+				// Opened even when it is refused, so that a stream of records can skip to its end
+				self.open.push((kind, at));
+				if depth >= self.options.max_depth {
+					return Err(AconError::ExcessiveDepth(at, self.options.max_depth));
+				}
+				let word = rest.split_whitespace().next().unwrap_or("");
+				let name = unescape_cow(word);
+				if name.len() > self.options.max_key_length {
+					return Err(AconError::ExcessiveKeyLength(span(word), self.options.max_key_length));
+				}
+				if kind == Kind::Table { Event::TableStart(name) } else { Event::ArrayStart(name) }
+			}
+			"}" | "]" => {
+				match self.open.pop() {
+					None => return Err(AconError::ExcessiveClosingDelimiter(at)),
+					Some((Kind::Array, opened)) if first != "]" => {
+						return Err(AconError::WrongClosingDelimiterExpectedArray(at, opened));
+					}
+					Some((Kind::Table, opened)) if first != "}" => {
+						return Err(AconError::WrongClosingDelimiterExpectedTable(at, opened));
+					}
+					Some(_) => Event::End,
+				}
+			}
+			"$" => {
+				self.open.clear();
+				Event::CloseAll
+			}
+			"#" => Event::Comment(Cow::Borrowed(rest.trim())),
+			_ => {
+				let in_array = matches!(self.open.last(), Some(&(Kind::Array, _)));
+				let raw = if in_array { line[at.start - line_offset..].trim() } else { rest.trim() };
+				let value = text(raw);
+				if value.len() > self.options.max_value_length {
+					return Err(AconError::ExcessiveValueLength(span(raw), self.options.max_value_length));
+				}
+				if in_array {
+					Event::ArrayItem(value)
+				} else {
+					let key = unescape_cow(first);
+					if key.len() > self.options.max_key_length {
+						return Err(AconError::ExcessiveKeyLength(at, self.options.max_key_length));
+					}
+					Event::Entry(key, value)
+				}
+			}
+		};
+		Ok(Some((at, event)))
+	}
+
+	/// The number of tables and arrays that are open
+	pub(crate) fn depth(&self) -> usize {
+		self.open.len()
+	}
+
+	/// Check that all tables and arrays are closed at the end of the input
+	pub(crate) fn finish(&self) -> Result<(), AconError> {
+		match self.open.last() {
+			Some(&(Kind::Array, opened)) => Err(AconError::TopNodeIsArray(opened)),
+			Some(&(_, opened)) => Err(AconError::MultipleTopNodes(opened)),
+			None => Ok(()),
+		}
+	}
+
+	/// Check that the start of the next line, which is still incomplete, is within the limit
+	/// of the input size
+	pub(crate) fn check_partial(&self, partial: &[u8]) -> Result<(), AconError> {
+		if self.offset + partial.len() > self.options.max_total_bytes {
+			return Err(self.excessive_input(partial));
+		}
+		Ok(())
+	}
+
+	/// The error for the next line going past the limit of the input size, which points at
+	/// the first byte past the limit
+	pub(crate) fn excessive_input(&self, raw: &[u8]) -> AconError {
+		let limit = self.options.max_total_bytes;
+		let within = &raw[..limit - self.offset];
+		let valid = str::from_utf8(within).unwrap_or_else(|error| {
+			str::from_utf8(&within[..error.valid_up_to()]).unwrap_or("")
+		});
+		let span = Span { line: self.line + 1, column: valid.chars().count() + 1, start: limit, end: limit + 1 };
+		AconError::ExcessiveInput(span, limit)
+	}
+
+	/// The span of the first invalid byte of the next line
+	pub(crate) fn invalid_utf8(&self, raw: &[u8], error: Utf8Error) -> Span {
+		let valid = str::from_utf8(&raw[..error.valid_up_to()]).unwrap_or("");
+		let start = self.offset + error.valid_up_to();
+		Span {
+			line: self.line + 1,
+			column: valid.chars().count() + 1,
+			start,
+			end: start + error.error_len().unwrap_or(raw.len() - error.valid_up_to()),
+		}
+	}
+}
+
+/// Collapse the whitespace between words into single spaces and unescape the result
+pub(crate) fn text(words: &str) -> Cow<'_, str> {
+	if words.is_empty() || words.split(' ').all(|word| !word.is_empty() && !word.contains(char::is_whitespace)) {
+		unescape_cow(words)
+	} else {
+		Cow::Owned(unescape(&words.split_whitespace().collect::<Vec<_>>().join(" ")))
+	}
+}
+
+/// A parser that yields the events of a string along with their spans
+///
+///  ```
+///  use acon::{Event, PullParser};
+///  let input = "{ table\n  key some   value\n}\n";
+///  let events = PullParser::new(input).map(|event| event.unwrap().1).collect::<Vec<_>>();
+///  assert_eq!(events, [
+///      Event::TableStart("table".into()),
+///      Event::Entry("key".into(), "some value".into()),
+///      Event::End,
+///  ]);
+///  ```
+///
+/// After an error, the parser yields no more events.
+pub struct PullParser<'a> {
+	lines: SplitInclusive<'a, char>,
+	state: State,
+	done: bool,
+}
+
+impl<'a> PullParser<'a> {
+	/// Create a parser for the input
+	pub fn new(input: &'a str) -> PullParser<'a> {
+		PullParser::with_options(input, ParserOptions::default())
+	}
+
+	/// Create a parser that enforces the limits of the options
+	pub(crate) fn with_options(input: &'a str, options: ParserOptions) -> PullParser<'a> {
+		PullParser { lines: input.split_inclusive('\n'), state: State::new(options), done: false }
+	}
+}
+
+impl<'a> Iterator for PullParser<'a> {
+	type Item = Result<(Span, Event<'a>), AconError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.done {
+			return None;
+		}
+		for line in self.lines.by_ref() {
+			match self.state.line(line) {
+				Ok(Some(event)) => return Some(Ok(event)),
+				Ok(None) => {}
+				Err(error) => {
+					self.done = true;
+					return Some(Err(error));
+				}
+			}
+		}
+		self.done = true;
+		self.state.finish().err().map(Err)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn events(input: &str) -> Vec<Result<(usize, Event<'_>), AconError>> {
+		PullParser::new(input).map(|event| event.map(|(span, event)| (span.line, event))).collect()
+	}
+
+	#[test]
+	fn all_events() {
+		let input = "# a comment  \n{ table extra\n\tkey  a(32)value  here \n\t[\n\t\tone\n\n\t]\n}\n[ array\n$ rest\nlast";
+		assert_eq!(events(input), vec![
+			Ok((1, Event::Comment("a comment".into()))),
+			Ok((2, Event::TableStart("table".into()))),
+			Ok((3, Event::Entry("key".into(), "a value here".into()))),
+			Ok((4, Event::ArrayStart("".into()))),
+			Ok((5, Event::ArrayItem("one".into()))),
+			Ok((6, Event::ArrayItem("".into()))),
+			Ok((7, Event::End)),
+			Ok((8, Event::End)),
+			Ok((9, Event::ArrayStart("array".into()))),
+			Ok((10, Event::CloseAll)),
+			Ok((11, Event::Entry("last".into(), "".into()))),
+		]);
+	}
+
+	#[test]
+	fn borrows_unchanged_text() {
+		let mut parser = PullParser::new("key two words\nother two  words\n");
+		match parser.next() {
+			Some(Ok((_, Event::Entry(Cow::Borrowed("key"), Cow::Borrowed("two words"))))) => {}
+			other => panic!("expected borrowed text, got {:?}", other),
+		}
+		match parser.next() {
+			Some(Ok((_, Event::Entry(Cow::Borrowed("other"), Cow::Owned(ref value))))) => assert_eq!(value, "two words"),
+			other => panic!("expected owned text, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn delimiter_errors() {
+		let error = events("{\n]\nkey value").pop().unwrap().unwrap_err();
+		assert!(matches!(error, AconError::WrongClosingDelimiterExpectedTable(span, opened)
+		                        if span.line == 2 && opened.line == 1));
+		assert!(matches!(events("}").pop(), Some(Err(AconError::ExcessiveClosingDelimiter(_)))));
+		assert!(matches!(events("{\n[").pop(), Some(Err(AconError::TopNodeIsArray(span))) if span.line == 2));
+		assert!(matches!(events("[\n{").pop(), Some(Err(AconError::MultipleTopNodes(span))) if span.line == 2));
+		assert_eq!(events("{\n]\nkey value").len(), 2);
+	}
+}
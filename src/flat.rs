@@ -0,0 +1,233 @@
+//! Flattening values into one line per leaf
+//!
+//! Every string in a value becomes a line of its full path and the string, so deep trees can
+//! be processed with tools that see one line at a time. Tables and arrays without entries
+//! become lines with the value `{}` and `[]`. The path is escaped like a key and the string
+//! like a value, so the first word of a line is its path and the rest of the line its value.
+
+use std::fmt;
+
+use event::text;
+use {escape_value, Acon, Array, Path, PathError, Segment, Table};
+
+/// Errors that come about when rebuilding a value from flattened lines
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct UnflattenError {
+	/// The line with the offending path, starting at 1
+	pub line: usize,
+	/// Why the path can not be set
+	pub error: PathError,
+}
+
+impl fmt::Display for UnflattenError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {}: {}", self.line, self.error)
+	}
+}
+
+impl std::error::Error for UnflattenError {}
+
+impl Acon {
+	/// Write a line of the path and the value of every string, and of every table and array
+	/// that has no entries.
+	///
+	///  ```
+	///  use acon::Acon;
+	///  let input = "{ server\n  host example.com\n  [ ports\n    80\n    443\n  ]\n  { tls\n  }\n}\n";
+	///  let acon = input.parse::<Acon>().unwrap();
+	///  let flat = acon.flatten();
+	///  assert_eq!(flat, "server.host example.com\nserver.ports.0 80\nserver.ports.1 443\nserver.tls {}\n");
+	///  assert_eq!(Acon::unflatten(&flat).unwrap(), acon);
+	///  ```
+	///
+	/// Strings that read as `{}` or `[]` are escaped, so they do not become empty tables and
+	/// arrays when the lines are unflattened.
+	pub fn flatten(&self) -> String {
+		let mut flat = String::new();
+		flatten_into(self, &mut Path::new(), &mut flat);
+		flat
+	}
+
+	/// Rebuild a value from the lines written by `flatten`. Blank lines and comments are
+	/// skipped. A line that starts with whitespace has an empty path, which is the key of an
+	/// unnamed table or array at the root.
+	///
+	/// Indices create arrays and other segments create tables, so a key consisting of digits
+	/// is written escaped as `flatten` does. An index must either be that of an element that
+	/// is already there, or be the next one. The segment `+` appends a new element to an array.
+	///
+	///  ```
+	///  use acon::Acon;
+	///  let acon = Acon::unflatten("# hosts\nservers.0.name web\nservers.+.name db\n(49) one\n").unwrap();
+	///  assert_eq!(acon.path("servers.1.name").unwrap().string(), "db");
+	///  assert_eq!(acon.get("1").unwrap().string(), "one");
+	///  assert!(Acon::unflatten("list.1 skips the first element").is_err());
+	///  ```
+	///
+	pub fn unflatten(flat: &str) -> Result<Acon, UnflattenError> {
+		let mut root = Acon::Table(Table::new());
+		for (index, line) in flat.lines().enumerate() {
+			let line = line.trim_end();
+			let (first, rest) = match line.find(char::is_whitespace) {
+				Some(end) => (&line[..end], line[end..].trim_start()),
+				None => (line, ""),
+			};
+			if line.is_empty() || first == "#" {
+				continue;
+			}
+			let value = match rest {
+				"{}" => Acon::Table(Table::new()),
+				"[]" => Acon::Array(Array::new()),
+				_ => Acon::String(text(rest).into_owned()),
+			};
+			set(&mut root, &Path::from(first), value).map_err(|error| UnflattenError { line: index + 1, error })?;
+		}
+		Ok(root)
+	}
+}
+
+fn flatten_into(acon: &Acon, path: &mut Path, flat: &mut String) {
+	match *acon {
+		Acon::Array(ref array) if array.is_empty() && !path.is_empty() => flat.push_str(&format!("{} []\n", path)),
+		Acon::Table(ref table) if table.is_empty() && !path.is_empty() => flat.push_str(&format!("{} {{}}\n", path)),
+		Acon::Array(ref array) => {
+			for (index, value) in array.iter().enumerate() {
+				path.push(index);
+				flatten_into(value, path, flat);
+				path.pop();
+			}
+		}
+		Acon::Table(ref table) => {
+			for (key, value) in table.iter() {
+				path.push(key.as_str());
+				flatten_into(value, path, flat);
+				path.pop();
+			}
+		}
+		Acon::String(ref string) => {
+			let escaped = match string.as_str() {
+				"{}" => "(123)}".to_string(),
+				"[]" => "(91)]".to_string(),
+				_ => escape_value(string),
+			};
+			if escaped.is_empty() {
+				flat.push_str(&format!("{}\n", path));
+			} else {
+				flat.push_str(&format!("{} {}\n", path, escaped));
+			}
+		}
+	}
+}
+
+/// Put the value at the path, creating the tables and arrays along the way
+fn set(root: &mut Acon, path: &Path, value: Acon) -> Result<(), PathError> {
+	let segments = path.iter().collect::<Vec<_>>();
+	let mut current = root;
+	let mut walked = Path::new();
+	for (segment, next) in segments.iter().zip(segments.iter().skip(1)) {
+		let missing = match **next {
+			Segment::Key(_) => Acon::Table(Table::new()),
+			Segment::Index(_) | Segment::Append => Acon::Array(Array::new()),
+		};
+		walked.push((*segment).clone());
+		current = child(current, segment, missing, &walked)?;
+	}
+	match segments.last() {
+		Some(last) => insert(current, last, value, &walked.join((*last).clone())),
+		None => Ok(()),
+	}
+}
+
+/// The child at the segment, which is created from `missing` if it is not there yet. The
+/// path includes the segment.
+fn child<'a>(acon: &'a mut Acon, segment: &Segment, missing: Acon, path: &Path) -> Result<&'a mut Acon, PathError> {
+	match *acon {
+		Acon::Array(ref mut array) => {
+			let index = index(array, segment, path)?;
+			if index == array.len() {
+				array.push(missing);
+			}
+			Ok(&mut array[index])
+		}
+		Acon::Table(ref mut table) => Ok(table.entry(segment.key().into_owned()).or_insert(missing)),
+		Acon::String(_) => Err(string(path)),
+	}
+}
+
+/// Insert the value at the segment, which must not be there yet
+fn insert(acon: &mut Acon, segment: &Segment, value: Acon, path: &Path) -> Result<(), PathError> {
+	match *acon {
+		Acon::Array(ref mut array) => {
+			if index(array, segment, path)? < array.len() {
+				return Err(PathError::Exists(path.to_string()));
+			}
+			array.push(value);
+		}
+		Acon::Table(ref mut table) => {
+			let key = segment.key().into_owned();
+			if table.contains_key(&key) {
+				return Err(PathError::Exists(path.to_string()));
+			}
+			table.insert(key, value);
+		}
+		Acon::String(_) => return Err(string(path)),
+	}
+	Ok(())
+}
+
+/// The index of an element that is there, or of the next element
+fn index(array: &Array, segment: &Segment, path: &Path) -> Result<usize, PathError> {
+	match *segment {
+		Segment::Index(index) if index <= array.len() => Ok(index),
+		Segment::Append => Ok(array.len()),
+		_ => Err(PathError::InvalidIndex(path.to_string())),
+	}
+}
+
+/// The error for a path that goes through the string before its last segment
+fn string(path: &Path) -> PathError {
+	let mut parent = path.clone();
+	parent.pop();
+	PathError::TraversesString(parent.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let input = "{ \n\t[ \n\t\t\n\t\t{\n\t\t}\n\t\t[\n\t\t]\n\t]\n\t1 named by a number\n}\n\
+		             a(32)key  two  words\nbraces (123)}\nbrackets [] x\nempty\nspaces (32)a(10)b(32)\n";
+		let acon = input.parse::<Acon>().unwrap();
+		let flat = acon.flatten();
+		assert_eq!(flat, "\
+			..0\n\
+			..1 {}\n\
+			..2 []\n\
+			.(49) named by a number\n\
+			a(32)key two words\n\
+			braces (123)}\n\
+			brackets [] x\n\
+			empty\n\
+			spaces (32)a(10)b(32)\n");
+		assert_eq!(Acon::unflatten(&flat).unwrap(), acon);
+		assert_eq!(Acon::unflatten("").unwrap(), Acon::Table(Table::new()));
+		assert_eq!(Acon::Table(Table::new()).flatten(), "");
+		let unnamed = "[ \n]\n".parse::<Acon>().unwrap();
+		assert_eq!(unnamed.flatten(), " []\n");
+		assert_eq!(Acon::unflatten(" []\n").unwrap(), unnamed);
+	}
+
+	#[test]
+	fn unflatten_errors() {
+		let error = |flat: &str| Acon::unflatten(flat).unwrap_err();
+		assert_eq!(error("a 1\n\na 2\n"), UnflattenError { line: 3, error: PathError::Exists("a".to_string()) });
+		assert_eq!(error("a 1\na.b 2\n").error, PathError::TraversesString("a".to_string()));
+		assert_eq!(error("a.0 x\na.2 y\n").error, PathError::InvalidIndex("a.2".to_string()));
+		assert_eq!(error("a.0 x\na.b y\n").error, PathError::InvalidIndex("a.b".to_string()));
+		assert_eq!(error("a.0 x\na.0 y\n").error, PathError::Exists("a.0".to_string()));
+		assert_eq!(error("a {}\na {}\n").error, PathError::Exists("a".to_string()));
+		assert_eq!(error("a.0 x\na.0 y\n").to_string(), "line 2: a.0: there already is a value");
+	}
+}
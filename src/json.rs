@@ -0,0 +1,159 @@
+//! Conversion between ACON and JSON
+//!
+//! Objects become tables and arrays become arrays. Every other JSON value becomes a string:
+//! numbers and booleans are written as in JSON, and `null` becomes an empty string, as `None`
+//! does when serializing. Converting back gives strings, unless scalars are inferred.
+//!
+//! An object can have a scalar under the empty key, but ACON can only write a table or array
+//! there, so `Acon::try_from_json` rejects such objects.
+
+use serde_json::{Map, Number, Value};
+
+use {Acon, Path, PathError, Table};
+
+impl Acon {
+	/// Convert a JSON value into ACON
+	///
+	///  ```
+	///  # extern crate acon;
+	///  # extern crate serde_json;
+	///  use acon::Acon;
+	///  let json = serde_json::from_str(r#"{"name": "web", "ports": [80, 443], "proxy": null, "tls": true}"#).unwrap();
+	///  let acon = Acon::from_json(&json);
+	///  assert_eq!(acon.to_string(), "name web\n[ ports\n\t80\n\t443\n]\nproxy\ntls true\n");
+	///  ```
+	///
+	/// A scalar under the empty key becomes a string that can not be written as ACON. Use
+	/// `try_from_json` for values that are written.
+	pub fn from_json(json: &Value) -> Acon {
+		match *json {
+			Value::Null => Acon::String(String::new()),
+			Value::Bool(boolean) => Acon::String(boolean.to_string()),
+			Value::Number(ref number) => Acon::String(number.to_string()),
+			Value::String(ref string) => Acon::String(string.clone()),
+			Value::Array(ref array) => Acon::Array(array.iter().map(Acon::from_json).collect()),
+			Value::Object(ref object) => {
+				Acon::Table(object.iter().map(|(key, value)| (key.clone(), Acon::from_json(value))).collect::<Table>())
+			}
+		}
+	}
+
+	/// Convert a JSON value into ACON, failing with the path of the first scalar under the
+	/// empty key of an object
+	///
+	///  ```
+	///  # extern crate acon;
+	///  # extern crate serde_json;
+	///  use acon::{Acon, PathError};
+	///  let json = serde_json::from_str(r#"{"a": [{"": "x"}]}"#).unwrap();
+	///  assert_eq!(Acon::try_from_json(&json), Err(PathError::Unwritable("a.0.".to_string())));
+	///  ```
+	///
+	pub fn try_from_json(json: &Value) -> Result<Acon, PathError> {
+		check(json, &mut Path::new())?;
+		Ok(Acon::from_json(json))
+	}
+
+	/// Convert the value into JSON. Strings stay strings, unless `infer` is set, which turns
+	/// the empty string into `null`, `true` and `false` into booleans, and strings that are
+	/// written exactly as JSON writes a number into numbers.
+	///
+	///  ```
+	///  # extern crate acon;
+	///  # extern crate serde_json;
+	///  use acon::Acon;
+	///  let acon = "large 1e3\nport 80\nproxy\nratio 0.5\ntls true\n".parse::<Acon>().unwrap();
+	///  assert_eq!(acon.to_json(false).to_string(),
+	///             r#"{"large":"1e3","port":"80","proxy":"","ratio":"0.5","tls":"true"}"#);
+	///  assert_eq!(acon.to_json(true).to_string(),
+	///             r#"{"large":"1e3","port":80,"proxy":null,"ratio":0.5,"tls":true}"#);
+	///  ```
+	///
+	pub fn to_json(&self, infer: bool) -> Value {
+		match *self {
+			Acon::String(ref string) if infer => scalar(string),
+			Acon::String(ref string) => Value::String(string.clone()),
+			Acon::Array(ref array) => Value::Array(array.iter().map(|value| value.to_json(infer)).collect()),
+			Acon::Table(ref table) => {
+				Value::Object(table.iter().map(|(key, value)| (key.clone(), value.to_json(infer))).collect::<Map<_, _>>())
+			}
+		}
+	}
+}
+
+/// Fail at the first scalar under the empty key of an object
+fn check(json: &Value, path: &mut Path) -> Result<(), PathError> {
+	match *json {
+		Value::Array(ref array) => {
+			for (index, value) in array.iter().enumerate() {
+				path.push(index);
+				check(value, path)?;
+				path.pop();
+			}
+		}
+		Value::Object(ref object) => {
+			for (key, value) in object {
+				path.push(key.as_str());
+				if key.is_empty() && !value.is_array() && !value.is_object() {
+					return Err(PathError::Unwritable(path.to_string()));
+				}
+				check(value, path)?;
+				path.pop();
+			}
+		}
+		_ => {}
+	}
+	Ok(())
+}
+
+/// Infer the JSON value that a string is written as. Numbers are only inferred if they are
+/// written back the same, so no digits are lost.
+fn scalar(string: &str) -> Value {
+	match string {
+		"" => Value::Null,
+		"true" => Value::Bool(true),
+		"false" => Value::Bool(false),
+		_ => match string.parse::<Number>() {
+			Ok(ref number) if number.to_string() == string => Value::Number(number.clone()),
+			_ => Value::String(string.to_string()),
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn inferred_scalars() {
+		let numbers = ["0", "-1", "18446744073709551615", "-9223372036854775808", "0.5", "-2.25"];
+		for number in &numbers {
+			assert!(scalar(number).is_number(), "{} is a number", number);
+		}
+		let strings = ["007", "+1", "1.", ".5", "1e3", "1.50", "NaN", "inf", "0x10", " 1", "True", "nul",
+		               "123456789012345678901234567890"];
+		for string in &strings {
+			assert_eq!(scalar(string), Value::String(string.to_string()));
+		}
+	}
+
+	#[test]
+	fn round_trip() {
+		let input = r#"{"": {"a": [[], {}, ""]}, "b": {"c": "x y", "d": 1.5, "e": false}, "f": [1, "1", null]}"#;
+		let json = input.parse::<Value>().unwrap();
+		let acon = Acon::from_json(&json);
+		assert_eq!(acon.to_string(), "{\n\t[ a\n\t\t[\n\t\t]\n\t\t{\n\t\t}\n\n\t]\n}\n\
+		                              { b\n\tc x y\n\td 1.5\n\te false\n}\n[ f\n\t1\n\t1\n\n]\n");
+		assert_eq!(acon.to_string().parse::<Acon>().unwrap(), acon);
+		assert_eq!(acon.to_json(false).to_string(),
+		           r#"{"":{"a":[[],{},""]},"b":{"c":"x y","d":"1.5","e":"false"},"f":["1","1",""]}"#);
+		assert_eq!(acon.to_json(true).to_string(),
+		           r#"{"":{"a":[[],{},null]},"b":{"c":"x y","d":1.5,"e":false},"f":[1,1,null]}"#);
+		assert_eq!(Acon::try_from_json(&json), Ok(acon));
+
+		let json = r#"{"": "x", "a": {"": "y"}}"#.parse::<Value>().unwrap();
+		assert_eq!(Acon::try_from_json(&json), Err(PathError::Unwritable("".to_string())));
+		let json = r#"{"a": {"": null}}"#.parse::<Value>().unwrap();
+		assert_eq!(Acon::try_from_json(&json), Err(PathError::Unwritable("a.".to_string())));
+	}
+}
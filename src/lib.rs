@@ -85,7 +85,8 @@
 //! Dots and whitespaces in keys also require escape codes.
 //! Escaping is done by inserting (number), where number is the numeric code point value.
 //! This library handles escaping transparently. To escape or unescape is only necessary for
-//! other utilities or viewing the data in another way.
+//! other utilities or viewing the data in another way, for which `escape`, `escape_value`,
+//! and `unescape` are provided.
 //! When using dot-pathing, you still need to explicitly write the parenthesized elements.
 //!
 //! ```rust
@@ -94,13 +95,15 @@
 //!   key(32)with_space(46)and_dot value(10)with(10)new(10)lines, which is interesting
 //! "#;
 //! let result = input.parse::<Acon>().unwrap();
-//! assert_eq!(result.path("key(32)with_space(46)and_dot").unwrap().string(), "value(10)with(10)new(10)lines, which is interesting");
+//! assert_eq!(result.path("key(32)with_space(46)and_dot").unwrap().string(), "value\nwith\nnew\nlines, which is interesting");
+//! assert!(result.to_string().contains("value(10)with(10)new(10)lines"));
 //! ```
 //!
 //! # Comments #
 //!
 //! A line is ignored if the first word is a '#'. If you need this to be the first word
 //! on a line, you can use the escape code '(35)'.
+//! To keep the comments of a document, parse it into `Commented` instead of `Acon`.
 //!
 
 #![deny(missing_docs)]
@@ -110,15 +113,72 @@
 #![feature(test)]
 
 extern crate test;
-
-use std::collections::BTreeMap;
+#[cfg(feature = "preserve_order")]
+extern crate indexmap;
+#[cfg(test)]
+extern crate quickcheck;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
+
+mod borrowed;
+mod comment;
+mod convert;
+mod document;
+#[cfg(feature = "serde")]
+pub mod de;
+mod error;
+mod escape;
+mod event;
+mod flat;
+#[cfg(feature = "json")]
+mod json;
+pub mod map;
+mod options;
+mod parse;
+mod path;
+mod push;
+mod query;
+mod records;
+#[cfg(feature = "serde")]
+pub mod ser;
+mod writer;
+
+pub use borrowed::{AconRef, ArrayRef, TableRef};
+pub use comment::{Commented, Comments};
+pub use convert::{ConvertError, parse_bool, parse_byte_size, parse_duration};
+pub use document::Document;
+#[cfg(feature = "serde")]
+pub use de::{from_acon, from_str, Deserializer};
+pub use error::{AconError, Kind, KindError, PathError, ReadError, Span, WriteError};
+#[cfg(feature = "serde")]
+pub use error::SerdeError;
+pub use escape::{escape, escape_value, unescape};
+pub use event::{Event, PullParser};
+pub use flat::UnflattenError;
+pub use map::Map;
+pub use options::{DuplicateKeys, ParserOptions};
+pub use path::{Path, Segment};
+pub use push::{Parsed, Parser};
+pub use query::{Query, QueryError};
+pub use records::Records;
+#[cfg(feature = "serde")]
+pub use ser::{to_acon, to_string, Serializer};
+pub use writer::AconWriter;
+
+use comment::Position;
 use std::str::FromStr;
 
 /// Vec of Acon values
 pub type Array = Vec<Acon>;
 
-/// `BTreeMap` of strings mapped to Acon
-pub type Table = BTreeMap<String, Acon>;
+/// `Map` of strings mapped to Acon
+pub type Table = Map<String, Acon>;
 
 /// Enumeration over all variable types in ACON
 #[derive(PartialEq, Clone, Debug)]
@@ -127,7 +187,7 @@ pub enum Acon {
 	Array(Array),
 	/// String type contains a simple std::string::String
 	String(String),
-	/// Table consists of a BTreeMap<String, Acon>
+	/// Table consists of a map of strings to Acon
 	Table(Table),
 }
 
@@ -157,8 +217,149 @@ impl Acon {
 		}
 	}
 
+	/// The kind of this value
+	pub fn kind(&self) -> Kind {
+		match *self {
+			Acon::Array(_) => Kind::Array,
+			Acon::String(_) => Kind::String,
+			Acon::Table(_) => Kind::Table,
+		}
+	}
+
+	/// Check if this value is an array
+	pub fn is_array(&self) -> bool {
+		self.kind() == Kind::Array
+	}
+
+	/// Check if this value is a string
+	pub fn is_string(&self) -> bool {
+		self.kind() == Kind::String
+	}
+
+	/// Check if this value is a table
+	pub fn is_table(&self) -> bool {
+		self.kind() == Kind::Table
+	}
+
+	/// Get the array if this value is an array
+	pub fn as_array(&self) -> Option<&Array> {
+		match *self {
+			Acon::Array(ref array) => Some(array),
+			_ => None,
+		}
+	}
+
+	/// Get the string if this value is a string
+	pub fn as_str(&self) -> Option<&str> {
+		match *self {
+			Acon::String(ref string) => Some(string),
+			_ => None,
+		}
+	}
+
+	/// Get the table if this value is a table
+	pub fn as_table(&self) -> Option<&Table> {
+		match *self {
+			Acon::Table(ref table) => Some(table),
+			_ => None,
+		}
+	}
+
+	/// Get the array mutably if this value is an array
+	pub fn as_array_mut(&mut self) -> Option<&mut Array> {
+		match *self {
+			Acon::Array(ref mut array) => Some(array),
+			_ => None,
+		}
+	}
+
+	/// Get the string mutably if this value is a string
+	pub fn as_str_mut(&mut self) -> Option<&mut String> {
+		match *self {
+			Acon::String(ref mut string) => Some(string),
+			_ => None,
+		}
+	}
+
+	/// Get the table mutably if this value is a table
+	pub fn as_table_mut(&mut self) -> Option<&mut Table> {
+		match *self {
+			Acon::Table(ref mut table) => Some(table),
+			_ => None,
+		}
+	}
+
+	/// Convert into the array if this value is an array
+	pub fn into_array(self) -> Option<Array> {
+		match self {
+			Acon::Array(array) => Some(array),
+			_ => None,
+		}
+	}
+
+	/// Convert into the string if this value is a string
+	pub fn into_string(self) -> Option<String> {
+		match self {
+			Acon::String(string) => Some(string),
+			_ => None,
+		}
+	}
+
+	/// Convert into the table if this value is a table
+	pub fn into_table(self) -> Option<Table> {
+		match self {
+			Acon::Table(table) => Some(table),
+			_ => None,
+		}
+	}
+
+	/// Get the array, or an error naming the kind of this value
+	///
+	///  ```
+	///  use acon::{Acon, Kind, KindError};
+	///  let result = "key value".parse::<Acon>().unwrap();
+	///  let error = result.path("key").unwrap().try_array().unwrap_err();
+	///  assert_eq!(error, KindError { expected: Kind::Array, actual: Kind::String });
+	///  assert_eq!(error.to_string(), "expected an array, found a string");
+	///  ```
+	///
+	pub fn try_array(&self) -> Result<&Array, KindError> {
+		let actual = self.kind();
+		self.as_array().ok_or(KindError { expected: Kind::Array, actual })
+	}
+
+	/// Get the string, or an error naming the kind of this value
+	pub fn try_str(&self) -> Result<&str, KindError> {
+		let actual = self.kind();
+		self.as_str().ok_or(KindError { expected: Kind::String, actual })
+	}
+
+	/// Get the table, or an error naming the kind of this value
+	pub fn try_table(&self) -> Result<&Table, KindError> {
+		let actual = self.kind();
+		self.as_table().ok_or(KindError { expected: Kind::Table, actual })
+	}
+
+	/// Get the array mutably, or an error naming the kind of this value
+	pub fn try_array_mut(&mut self) -> Result<&mut Array, KindError> {
+		let actual = self.kind();
+		self.as_array_mut().ok_or(KindError { expected: Kind::Array, actual })
+	}
+
+	/// Get the string mutably, or an error naming the kind of this value
+	pub fn try_str_mut(&mut self) -> Result<&mut String, KindError> {
+		let actual = self.kind();
+		self.as_str_mut().ok_or(KindError { expected: Kind::String, actual })
+	}
+
+	/// Get the table mutably, or an error naming the kind of this value
+	pub fn try_table_mut(&mut self) -> Result<&mut Table, KindError> {
+		let actual = self.kind();
+		self.as_table_mut().ok_or(KindError { expected: Kind::Table, actual })
+	}
+
 	/// Retrieve a reference to an entry via its path
-	/// Paths are dot-separated.
+	/// Paths are dot-separated, and each part is unescaped before it is looked up.
 	///
 	///  ```
 	///  use acon::Acon;
@@ -172,34 +373,136 @@ impl Acon {
 	///  assert_eq!(result.path("table.array.0").unwrap().string(), "value");
 	///  ```
 	///
-	pub fn path(&self, path: &str) -> Option<&Acon> {
-		let paths = path.split('.');
+	pub fn path<P: Into<Path>>(&self, path: P) -> Option<&Acon> {
 		let mut current = self;
-		for path in paths {
-			let owned = current;
-			current = match owned.get(path) {
-				Some(ref acon) => acon,
-				None => return None,
-			}
+		for segment in &path.into() {
+			current = current.get_segment(segment)?;
 		}
 		Some(current)
 	}
 
 	/// Retrieve a mutable reference to an entry via its path.
-	/// Paths are dot-separated.
-	pub fn path_mut(&mut self, path: &str) -> Option<&mut Acon> {
-		let paths = path.split('.');
+	/// Paths are dot-separated, and each part is unescaped before it is looked up.
+	pub fn path_mut<P: Into<Path>>(&mut self, path: P) -> Option<&mut Acon> {
 		let mut current = self;
-		for path in paths {
-			let owned = current;
-			current = match owned.get_mut(path) {
-				Some(acon) => acon,
-				None => return None,
-			}
+		for segment in &path.into() {
+			current = current.get_segment_mut(segment)?;
 		}
 		Some(current)
 	}
 
+	/// Set the value at a path, returning the value that was there before.
+	///
+	/// Tables that are missing along the path are created. In an array, the segment `+`
+	/// appends a new element, and an index replaces an existing element.
+	///
+	///  ```
+	///  use acon::Acon;
+	///  let mut result = "[ servers\n]".parse::<Acon>().unwrap();
+	///  result.set_path("a.b.c", Acon::String("value".to_string())).unwrap();
+	///  result.set_path("servers.+.host", Acon::String("example.com".to_string())).unwrap();
+	///  assert_eq!(result.path("a.b.c").unwrap().string(), "value");
+	///  assert_eq!(result.path("servers.0.host").unwrap().string(), "example.com");
+	///  ```
+	///
+	pub fn set_path<P: Into<Path>>(&mut self, path: P, value: Acon) -> Result<Option<Acon>, PathError> {
+		self.put_path(path.into(), value, true)
+	}
+
+	/// Insert a value at a path, failing if there already is a value there.
+	/// Missing tables are created as in `set_path`.
+	pub fn insert_path<P: Into<Path>>(&mut self, path: P, value: Acon) -> Result<(), PathError> {
+		self.put_path(path.into(), value, false).map(|_| ())
+	}
+
+	/// Remove the value at a path and return it. Elements after a removed array element
+	/// are shifted down.
+	pub fn remove_path<P: Into<Path>>(&mut self, path: P) -> Option<Acon> {
+		let mut path = path.into();
+		let last = path.pop()?;
+		match *self.path_mut(&path)? {
+			Acon::Array(ref mut array) => {
+				let index = match last {
+					Segment::Index(index) => index,
+					Segment::Key(ref key) => key.parse::<usize>().ok()?,
+					Segment::Append => return None,
+				};
+				if index < array.len() { Some(array.remove(index)) } else { None }
+			}
+			Acon::String(_) => None,
+			Acon::Table(ref mut table) => table.remove(last.key().as_ref()),
+		}
+	}
+
+	fn put_path(&mut self, mut path: Path, value: Acon, replace: bool) -> Result<Option<Acon>, PathError> {
+		check_writable(&value, &mut path.clone())?;
+		let last = match path.pop() {
+			Some(last) => last,
+			None if value.is_table() => return Ok(Some(std::mem::replace(self, value))),
+			None => return Err(PathError::Unwritable(String::new())),
+		};
+		let mut current = self;
+		let mut parent = Path::new();
+		for segment in &path {
+			current = current.child_or_create(segment, &parent)?;
+			parent.push(segment.clone());
+		}
+		let walked = parent.join(last.clone());
+		match *current {
+			Acon::Array(ref mut array) => {
+				let index = match last {
+					Segment::Append => {
+						array.push(value);
+						return Ok(None);
+					}
+					Segment::Index(index) => Some(index),
+					Segment::Key(ref key) => key.parse::<usize>().ok(),
+				};
+				match index {
+					Some(index) if index < array.len() && replace => {
+						Ok(Some(std::mem::replace(&mut array[index], value)))
+					}
+					Some(index) if index < array.len() => Err(PathError::Exists(walked.to_string())),
+					_ => Err(PathError::InvalidIndex(walked.to_string())),
+				}
+			}
+			Acon::String(_) => Err(PathError::TraversesString(parent.to_string())),
+			Acon::Table(ref mut table) => {
+				let key = last.key().into_owned();
+				if key.is_empty() && value.is_string() {
+					return Err(PathError::Unwritable(walked.to_string()));
+				}
+				if !replace && table.contains_key(&key) {
+					return Err(PathError::Exists(walked.to_string()));
+				}
+				Ok(table.insert(key, value))
+			}
+		}
+	}
+
+	/// Get the entry of a table or array, creating a table if it is missing. The path of
+	/// this value is used for errors.
+	fn child_or_create(&mut self, segment: &Segment, path: &Path) -> Result<&mut Acon, PathError> {
+		match *self {
+			Acon::Array(ref mut array) => {
+				let index = match *segment {
+					Segment::Append => {
+						array.push(Acon::Table(Table::new()));
+						array.len() - 1
+					}
+					Segment::Index(index) => index,
+					Segment::Key(ref key) => key.parse::<usize>().unwrap_or(usize::MAX),
+				};
+				let invalid = PathError::InvalidIndex(path.join(segment.clone()).to_string());
+				array.get_mut(index).ok_or(invalid)
+			}
+			Acon::String(_) => Err(PathError::TraversesString(path.to_string())),
+			Acon::Table(ref mut table) => {
+				Ok(table.entry(segment.key().into_owned()).or_insert_with(|| Acon::Table(Table::new())))
+			}
+		}
+	}
+
 	/// Retrieve a reference to an entry
 	pub fn get(&self, path: &str) -> Option<&Acon> {
 		match *self {
@@ -229,125 +532,164 @@ impl Acon {
 	}
 }
 
-/// Errors that come about during parsing
-#[derive(PartialEq, Clone, Debug)]
-pub enum AconError {
-	/// Indicates that there are too many closing delimiters compared to opening
-	/// delimiters
-	ExcessiveClosingDelimiter(Option<usize>),
-	/// Acon::String is the top of the stack. This indicates an interal error
-	InternalStringTop(Option<usize>),
-	/// The stack top is missing, indicating that something popped the top
-	MissingStackTop(Option<usize>),
-	/// There is more than one top node after parsing the input. Unterminated tables.
-	MultipleTopNodes,
-	/// If the top node of the stack is an array, this indicates that there's an
-	/// unterminated array
-	TopNodeIsArray,
-	/// The key at this line is already present in the parent table
-	OverwritingKey(Option<usize>),
-	/// Got a } but expected a ]
-	WrongClosingDelimiterExpectedArray(Option<usize>),
-	/// Got a ] but expected a }
-	WrongClosingDelimiterExpectedTable(Option<usize>),
+impl std::fmt::Display for Acon {
+	/// Serialize the value into ACON text
+	///
+	/// Parsing the output yields the original value for every tree that the parser can
+	/// produce. A table is written as its entries. Since the root of a document is always a
+	/// table, an array is written as the lines of an array body, and a string as a single
+	/// such line.
+	///
+	/// Only tables and arrays can have an empty key, since a line can not start with a
+	/// string value. The parser never produces a string under an empty key, and `set_path`,
+	/// `insert_path`, `to_string`, `try_from_json`, `AconWriter` and `Document` refuse to
+	/// write one. Such a string can only be put into a table directly, and is written as a
+	/// line that does not parse back into it.
+	///
+	///  ```
+	///  use acon::Acon;
+	///  let input = r#"
+	///    [ list
+	///      first
+	///      { named
+	///        key value
+	///      }
+	///    ]
+	///  "#;
+	///  let result = input.parse::<Acon>().unwrap();
+	///  assert_eq!(result.to_string().parse::<Acon>().unwrap(), result);
+	///  ```
+	///
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write_root(self, f, &mut Position::without_comments())
+	}
 }
 
-#[allow(dead_code)]
-impl AconError {
-	/// Prints a human-friendly error string for the given parse error.
-	fn reason(&self) -> String {
-		use AconError::*;
-		match *self {
-			ExcessiveClosingDelimiter(line) => {
-				let first = match line { Some(line) => format!("On line {}, t", line), None => "T".to_string() };
-				format!("{}here's a closing delimiter that has no matching opening delimiter. Note that
-all delimiters must be the first word on a line to count as such. The only delimiters are {}, {}, [, ], and $.",
-				first, "{", "}")
-			}
-			InternalStringTop(line) => {
-				let first = match line { Some(line) => format!("On line {}, t", line), None => "T".to_string() };
-				format!("{}here's a string on the top of the internal parse stack. This is impossible unless there is a
-bug in the parser. Please report this along with the input to the repository maintainer of ACON.", first)
-			}
-			MissingStackTop(line) => {
-				let first = match line { Some(line) => format!("On line {}, t", line), None => "T".to_string() };
-				format!("{}he top of the stack is missing. This indicates an internal error, as it's never supposed to
-happen. Please contact the maintainer of the ACON repository.", first)
-			}
-			MultipleTopNodes => {
-				"There is an unterminated table, you can append '$' to the input or try terminating it by finding a flaw in the input.".to_string()
+/// Fail at the first string under an empty key in the value, which can not be written. The
+/// path is that of the value.
+pub(crate) fn check_writable(acon: &Acon, path: &mut Path) -> Result<(), PathError> {
+	match *acon {
+		Acon::Array(ref array) => {
+			for (index, value) in array.iter().enumerate() {
+				path.push(index);
+				check_writable(value, path)?;
+				path.pop();
 			}
-			TopNodeIsArray => {
-				"The top of the stack is an array. This indicates that there is an unterminated array all the way
-until the end of the input. Try appending a ']' to the input to see if this solves the issue.".to_string()
-			}
-			OverwritingKey(line) => {
-				let first = match line { Some(line) => format!("On line {}, t", line), None => "T".to_string() };
-				format!("{}he key is already present in the table.", first)
+		}
+		Acon::String(_) => {}
+		Acon::Table(ref table) => {
+			for (key, value) in table {
+				path.push(key.as_str());
+				if key.is_empty() && value.is_string() {
+					return Err(PathError::Unwritable(path.to_string()));
+				}
+				check_writable(value, path)?;
+				path.pop();
 			}
-			WrongClosingDelimiterExpectedArray(line) => {
-				let first = match line { Some(line) => format!("On line {}, t", line), None => "T".to_string() };
-				format!("{}he closing delimiter did not match the array closing delimiter ]. Make sure all delimiters
-match up in the input. Some editors can help you by jumping from/to each delimiter.", first)
+		}
+	}
+	Ok(())
+}
+
+/// Write a value as the root of a document, along with the comments at each position
+pub(crate) fn write_root(acon: &Acon, f: &mut std::fmt::Formatter, position: &mut Position) -> std::fmt::Result {
+	match *acon {
+		Acon::Array(ref array) => {
+			for (index, value) in array.iter().enumerate() {
+				write_element(index, value, f, 0, position)?;
 			}
-			WrongClosingDelimiterExpectedTable(line) => {
-				let first = match line { Some(line) => format!("On line {}, t", line), None => "T".to_string() };
-				format!("{}he closing delimiter did not match the table closing delimiter {}. Make sure all delimiters
-until the end of the input. Try appending a ']' to the input to see if this solves the issue.", first, "}")
+		}
+		Acon::String(_) => {
+			write_element(0, acon, f, 0, position)?;
+		}
+		Acon::Table(ref table) => {
+			for (key, value) in table {
+				write_entry(key, value, f, 0, position)?;
 			}
 		}
 	}
-}
+	return position.trailing(f, 0);
 
-impl std::fmt::Display for Acon {
-	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-		match *self {
+	fn indent(f: &mut std::fmt::Formatter, depth: usize) -> std::fmt::Result {
+		for _ in 0..depth {
+			f.write_str("\t")?;
+		}
+		Ok(())
+	}
+
+	// Write a keyed entry of a table
+	fn write_entry(key: &str, acon: &Acon, f: &mut std::fmt::Formatter,
+	               depth: usize, position: &mut Position) -> std::fmt::Result {
+		position.enter(key);
+		position.leading(f, depth)?;
+		write_value(key, acon, f, depth, position)?;
+		position.leave();
+		Ok(())
+	}
+
+	// Write the lines of a value with its name or key
+	fn write_value(key: &str, acon: &Acon, f: &mut std::fmt::Formatter,
+	               depth: usize, position: &mut Position) -> std::fmt::Result {
+		indent(f, depth)?;
+		match *acon {
+			Acon::Array(ref array) => {
+				write_opening("[", key, f)?;
+				for (index, value) in array.iter().enumerate() {
+					write_element(index, value, f, depth + 1, position)?;
+				}
+				position.trailing(f, depth + 1)?;
+				indent(f, depth)?;
+				f.write_str("]\n")
+			}
+			Acon::String(ref string) if string.is_empty() => {
+				writeln!(f, "{}", escape(key))
+			}
+			Acon::String(ref string) => {
+				writeln!(f, "{} {}", escape(key), escape_value(string))
+			}
 			Acon::Table(ref table) => {
+				write_opening("{", key, f)?;
 				for (key, value) in table {
-					try!(recurse(key, value, f, 0));
+					write_entry(key, value, f, depth + 1, position)?;
 				}
-			}
-			_ => {
-				return Err(std::fmt::Error);
+				position.trailing(f, depth + 1)?;
+				indent(f, depth)?;
+				f.write_str("}\n")
 			}
 		}
-		return Ok(());
+	}
 
-		fn recurse(key: &str, acon: &Acon, f: &mut std::fmt::Formatter,
-		           depth: usize) -> std::fmt::Result {
-			let indent = String::from_utf8(vec![b'\t'; depth]).unwrap();
-			macro_rules! wrt {
-				( $( $x:expr ),* ) => {{
-					try!(f.write_str(&indent));
-					$(try!(f.write_str($x));)*
-				}
-				};
+	// Write an element of an array. The parser wraps a named table or array inside an
+	// array into a table of one entry, so such a table is written in the named form.
+	fn write_element(index: usize, acon: &Acon, f: &mut std::fmt::Formatter,
+	                 depth: usize, position: &mut Position) -> std::fmt::Result {
+		position.enter(index);
+		position.leading(f, depth)?;
+		match *acon {
+			Acon::Array(_) => write_value("", acon, f, depth, position)?,
+			Acon::String(ref string) if string.is_empty() => {
+				f.write_str("\n")?
 			}
-			macro_rules! nl {
-				() => { try!(f.write_str("\n")); }
+			Acon::String(ref string) => {
+				indent(f, depth)?;
+				writeln!(f, "{}", escape_value(string))?
 			}
-			match *acon {
-				Acon::Array(ref array) => {
-					wrt!("[ ", key, "\n");
-					for value in array {
-						try!(recurse("", value, f, depth + 1));
-					}
-					nl!();
-					wrt!("]\n");
-				}
-				Acon::String(ref string) => {
-					wrt!(key, " ", string, "\n");
-				}
-				Acon::Table(ref table) => {
-					wrt!("{ ", key, "\n");
-					for (key, value) in table {
-						try!(recurse(key, value, f, depth + 1));
-					}
-					nl!();
-					wrt!("}\n");
+			Acon::Table(ref table) => match table.iter().next() {
+				Some((key, value)) if table.len() == 1 && !key.is_empty() && !matches!(*value, Acon::String(_)) => {
+					write_entry(key, value, f, depth, position)?
 				}
-			}
-			Ok(())
+				_ => write_value("", acon, f, depth, position)?,
+			},
+		}
+		position.leave();
+		Ok(())
+	}
+
+	fn write_opening(delimiter: &str, name: &str, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		if name.is_empty() {
+			writeln!(f, "{}", delimiter)
+		} else {
+			writeln!(f, "{} {}", delimiter, escape(name))
 		}
 	}
 }
@@ -374,192 +716,16 @@ impl FromStr for Acon {
 	///  ```
 	///
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		let mut stack = vec![];
-		let lines = s.lines();
-		let mut current_line = 0usize;
-		push_base_table(&mut stack);
-
-		for line in lines {
-			current_line += 1;
-
-			let mut words = line.split_whitespace();
-
-			let mut first = None;
-			if let Some(word) = words.next() {
-				first = Some(word);
-				match word {
-					"{" => { push_table(&mut words, &mut stack); continue; }
-					"[" => { push_array(&mut words, &mut stack); continue; }
-					word @ "}" | word @ "]" => { try!(close_array_or_table(word, &mut stack, current_line)); continue; }
-					"$" => { try!(close_all_nestings(&mut stack, current_line)); continue; }
-					"#" => continue,
-					_ => { }
-				}
-			}
-
-			if let Some(top) = stack.last_mut() {
-				match top.value {
-					Acon::Array(ref mut array)
-						=> { append_line_to_top_array(array, &first, &mut words); }
-					Acon::String(_)
-						=> return Err(AconError::InternalStringTop(Some(current_line))),
-					Acon::Table(ref mut table)
-						=> { try!(append_entry_to_top_table(table, &first, &mut words, current_line)); }
-				}
-			} else {
-				return Err(AconError::MissingStackTop(Some(current_line)));
-			}
-		}
-
-		return {
-			if let Some(node) = stack.pop() {
-				match node.value {
-					Acon::Array(_) => Err(AconError::TopNodeIsArray),
-					Acon::String(_) => Err(AconError::InternalStringTop(Some(current_line))),
-					Acon::Table(table) => {
-						if !stack.is_empty() {
-							Err(AconError::MultipleTopNodes)
-						} else {
-							Ok(Acon::Table(table))
-						}
-					}
-				}
-			} else {
-				Err(AconError::MissingStackTop(None))
-			}
-		};
-
-
-		// BEGIN HELPER STRUCTURE ////////////////////////////////////////////
-		use std::str::SplitWhitespace;
-		struct Node {
-			name: String,
-			value: Acon,
-		}
-		// END HELPER STRUCTURE //////////////////////////////////////////////
-
-		// BEGIN HELPER FUNCTIONS ////////////////////////////////////////////
-		fn push_base_table(stack: &mut Vec<Node>) {
-			stack.push(Node {
-				name: "".to_string(),
-				value: Acon::Table(Table::new()),
-			});
-		}
-
-		fn push_array(words: &mut SplitWhitespace, stack: &mut Vec<Node>) {
-			let name = words.next().unwrap_or("");
-			stack.push(Node {
-				name: name.to_string(),
-				value: Acon::Array(Array::new()),
-			});
-		}
-
-		fn push_table(words: &mut SplitWhitespace, stack: &mut Vec<Node>) {
-			let name = words.next().unwrap_or("");
-			stack.push(Node {
-				name: name.to_string(),
-				value: Acon::Table(Table::new()),
-			});
-		}
-
-		fn close_all_nestings(stack: &mut Vec<Node>, line: usize) -> Result<(), AconError> {
-			while stack.len() > 1 {
-				if let Some(top) = stack.pop() {
-					if let Some(node) = stack.last_mut() {
-						match node.value {
-							Acon::Array(ref mut array) => {
-								if top.name == "" {
-									array.push(top.value);
-								} else {
-									let mut new = Table::new();
-									new.insert(top.name, top.value);
-									array.push(Acon::Table(new));
-								}
-							}
-							Acon::String(_) => { return Err(AconError::InternalStringTop(Some(line))); }
-							Acon::Table(ref mut table) => {
-								if table.contains_key(&top.name) {
-									return Err(AconError::OverwritingKey(Some(line)));
-								}
-								table.insert(top.name, top.value);
-							}
-						}
-					}
-				}
-			}
-			Ok(())
-		}
-
-		fn close_array_or_table(word: &str, stack: &mut Vec<Node>, line: usize) -> Result<(), AconError> {
-			if let Some(top) = stack.pop() {
-				match top.value {
-					Acon::Array(_) if word != "]"
-						=> return Err(AconError::WrongClosingDelimiterExpectedArray(Some(line))),
-					Acon::String(_) if word != "]"
-						=> return Err(AconError::InternalStringTop(Some(line))),
-					Acon::Table(_) if word != "}"
-						=> return Err(AconError::WrongClosingDelimiterExpectedTable(Some(line))),
-					_ => {}
-				}
-				if let Some(node) = stack.last_mut() {
-					match node.value {
-						Acon::Array(ref mut array) => {
-							if top.name == "" {
-								array.push(top.value);
-							} else {
-								let mut new = Table::new();
-								new.insert(top.name, top.value);
-								array.push(Acon::Table(new));
-							}
-						}
-						Acon::String(_) => { return Err(AconError::InternalStringTop(Some(line))); }
-						Acon::Table(ref mut table) => {
-							if table.contains_key(&top.name) {
-								return Err(AconError::OverwritingKey(Some(line)));
-							}
-							table.insert(top.name, top.value);
-						}
-					}
-					Ok(())
-				} else {
-					Err(AconError::ExcessiveClosingDelimiter(Some(line)))
-				}
-			} else {
-				Err(AconError::MissingStackTop(Some(line)))
-			}
-		}
-
-		fn append_line_to_top_array(array: &mut Array,
-		                            first: &Option<&str>,
-		                            words: &mut SplitWhitespace) {
-			let first = first.unwrap_or("");
-			let acc = words.fold(first.to_string(), |acc, x| acc + " " + x);
-			let acc = acc.trim();
-			array.push(Acon::String(acc.to_string()));
-		}
-
-		fn append_entry_to_top_table(table: &mut Table,
-		                             first: &Option<&str>,
-		                             words: &mut SplitWhitespace,
-		                             line: usize) -> Result<(), AconError> {
-			if let Some(ref key) = *first {
-				if table.contains_key(&key.to_string()) {
-					return Err(AconError::OverwritingKey(Some(line)));
-				}
-				let acc = words.fold("".to_string(), |acc, x| acc + " " + x);
-				let acc = acc.trim();
-				table.insert(key.to_string(), Acon::String(acc.to_string()));
-			}
-			Ok(())
-		}
-		// END HELPER FUNCTIONS //////////////////////////////////////////////
-
+		ParserOptions::new().parse(s)
 	}
 }
 
 #[cfg(test)]
+// The baseline tests and benches predate these lints
+#[allow(clippy::bool_assert_comparison, clippy::print_literal, clippy::unit_arg)]
 mod tests {
 	use super::*;
+	use quickcheck::{Arbitrary, Gen, QuickCheck};
 	use test::{Bencher, black_box};
 
 	fn key_eq(input: &str, key: &str, string: &str) -> Acon {
@@ -581,7 +747,7 @@ mod tests {
 			key2 value4
 		"#;
 		let acon = value.parse::<Acon>();
-		assert_eq!(acon, Err(AconError::OverwritingKey(Some(4))));
+		assert!(matches!(acon, Err(AconError::OverwritingKey(span, None)) if span.line == 4));
 	}
 
 	#[test]
@@ -594,7 +760,7 @@ mod tests {
 			key2 value4
 		"#;
 		let acon = value.parse::<Acon>();
-		assert_eq!(acon, Err(AconError::OverwritingKey(Some(5))));
+		assert!(matches!(acon, Err(AconError::OverwritingKey(span, Some(_))) if span.line == 5));
 	}
 
 	#[test]
@@ -607,7 +773,7 @@ mod tests {
 			key2 value4
 		"#;
 		let acon = value.parse::<Acon>();
-		assert_eq!(acon, Err(AconError::OverwritingKey(Some(5))));
+		assert!(matches!(acon, Err(AconError::OverwritingKey(span, Some(_))) if span.line == 5));
 	}
 
 	#[test]
@@ -623,7 +789,7 @@ mod tests {
 			}
 		"#;
 		let acon = value.parse::<Acon>();
-		assert_eq!(acon, Err(AconError::OverwritingKey(Some(7))));
+		assert!(matches!(acon, Err(AconError::OverwritingKey(span, None)) if span.line == 7));
 	}
 
 	#[test]
@@ -705,7 +871,7 @@ mod tests {
 		$
 		"#;
 		let acon = value.parse::<Acon>();
-		assert_eq!(acon, Err(AconError::OverwritingKey(Some(8))));
+		assert!(matches!(acon, Err(AconError::OverwritingKey(span, Some(_))) if span.line == 8));
 	}
 
 	#[test]
@@ -716,7 +882,7 @@ mod tests {
 
 		"#;
 		let acon = value.parse::<Acon>();
-		assert_eq!(acon, Err(AconError::TopNodeIsArray));
+		assert!(matches!(acon, Err(AconError::TopNodeIsArray(span)) if span.line == 2));
 	}
 
 	#[test]
@@ -727,7 +893,7 @@ mod tests {
 
 		"#;
 		let acon = value.parse::<Acon>();
-		assert_eq!(acon, Err(AconError::MultipleTopNodes));
+		assert!(matches!(acon, Err(AconError::MultipleTopNodes(span)) if span.line == 2));
 	}
 
 	#[test]
@@ -931,6 +1097,262 @@ mod tests {
 		assert_eq!(parsed.table().contains_key("$"), false);
 	}
 
+	#[test]
+	fn escaped_names_keys_and_values() {
+		let value = r#"
+			{ my(32)table
+				key(46)with(46)dots line(10)break
+				[ (91)
+					(35) not a comment
+					(36)
+				]
+			}
+		"#;
+		let acon = key_eq(value, "my table.key(46)with(46)dots", "line\nbreak");
+		key_eqt(&acon, "my(32)table.[.0", "# not a comment");
+		key_eqt(&acon, "my(32)table.[.1", "$");
+	}
+
+	#[test]
+	fn display_escapes() {
+		let mut table = Table::new();
+		table.insert("a key".to_string(), Acon::String(" padded\tvalue ".to_string()));
+		table.insert("#".to_string(), Acon::String("$".to_string()));
+		let acon = Acon::Table(table);
+		let string = acon.to_string();
+		assert!(string.contains("(35) (36)\n"));
+		assert!(string.contains("a(32)key (32)padded(9)value(32)\n"));
+		assert_eq!(string.parse::<Acon>(), Ok(acon));
+	}
+
+	#[test]
+	fn typed_accessors() {
+		let mut acon = "key value\n[ array\n]\n{ table\n}".parse::<Acon>().unwrap();
+		assert!(acon.is_table());
+		assert_eq!(acon.path("key").and_then(Acon::as_str), Some("value"));
+		assert_eq!(acon.path("key").and_then(Acon::as_table), None);
+		assert_eq!(acon.path("array").map(Acon::is_array), Some(true));
+		assert_eq!(acon.path("table").and_then(Acon::as_array), None);
+		assert_eq!(acon.path("table").unwrap().try_str(),
+		           Err(KindError { expected: Kind::String, actual: Kind::Table }));
+
+		acon.path_mut("key").and_then(Acon::as_str_mut).unwrap().push('s');
+		acon.path_mut("array").unwrap().try_array_mut().unwrap().push(Acon::String("x".to_string()));
+		assert!(acon.path_mut("array").unwrap().try_table_mut().is_err());
+		key_eqt(&acon, "key", "values");
+		key_eqt(&acon, "array.0", "x");
+
+		let table = acon.clone().into_table().unwrap();
+		assert_eq!(table.get("array").cloned().and_then(Acon::into_array).map(|array| array.len()), Some(1));
+		assert_eq!(acon.clone().into_string(), None);
+		assert_eq!(acon.into_array(), None);
+	}
+
+	#[test]
+	fn set_and_remove_paths() {
+		let mut acon = "key value\n[ array\n\tfirst\n]".parse::<Acon>().unwrap();
+		let string = |value: &str| Acon::String(value.to_string());
+
+		assert_eq!(acon.set_path("key", string("other")), Ok(Some(string("value"))));
+		assert_eq!(acon.set_path("a.b(46)c.d", string("deep")), Ok(None));
+		key_eqt(&acon, "a.b(46)c.d", "deep");
+		assert!(acon.path("a").unwrap().table().contains_key("b.c"));
+
+		assert_eq!(acon.set_path("array.+", string("second")), Ok(None));
+		assert_eq!(acon.set_path("array.0", string("zeroth")), Ok(Some(string("first"))));
+		assert_eq!(acon.set_path("array.+.x", string("y")), Ok(None));
+		key_eqt(&acon, "array.1", "second");
+		key_eqt(&acon, "array.2.x", "y");
+
+		assert_eq!(acon.set_path("key.sub", string("x")), Err(PathError::TraversesString("key".to_string())));
+		assert_eq!(acon.set_path("key.sub.more", string("x")), Err(PathError::TraversesString("key".to_string())));
+		assert_eq!(acon.set_path("array.3", string("x")), Err(PathError::InvalidIndex("array.3".to_string())));
+		assert_eq!(acon.set_path("array.x.y", string("x")), Err(PathError::InvalidIndex("array.x".to_string())));
+		assert_eq!(acon.insert_path("a.b(46)c.d", string("x")), Err(PathError::Exists("a.b(46)c.d".to_string())));
+		assert_eq!(acon.insert_path("array.0", string("x")), Err(PathError::Exists("array.0".to_string())));
+		assert_eq!(acon.insert_path("a.new", string("x")), Ok(()));
+		assert_eq!(acon.set_path(Path::new(), string("x")), Err(PathError::Unwritable(String::new())));
+		assert_eq!(acon.set_path("a.", string("x")), Err(PathError::Unwritable("a.".to_string())));
+		let nested = Acon::Table(vec![("".to_string(), string("x"))].into_iter().collect());
+		assert_eq!(acon.insert_path("array.+", nested), Err(PathError::Unwritable("array.+.".to_string())));
+		assert!(acon.path("a.").is_none());
+
+		assert_eq!(acon.remove_path("array.0"), Some(string("zeroth")));
+		key_eqt(&acon, "array.0", "second");
+		assert_eq!(acon.remove_path("array.5"), None);
+		assert_eq!(acon.remove_path("a.b(46)c"), Some(Acon::Table(vec![("d".to_string(), string("deep"))].into_iter().collect())));
+		assert_eq!(acon.remove_path("key.sub"), None);
+		assert_eq!(acon.remove_path("key"), Some(string("other")));
+		assert_eq!(acon.path("key"), None);
+	}
+
+	#[test]
+	fn error_trait_object() {
+		fn parse(input: &str) -> Result<Acon, Box<dyn std::error::Error>> {
+			Ok(input.parse::<Acon>()?)
+		}
+		let error = parse("{ table\n]").unwrap_err();
+		assert_eq!(error.to_string(), "{ table\n]".parse::<Acon>().unwrap_err().reason());
+		assert!(error.to_string().starts_with("On line 2, the closing delimiter did not match the table"));
+		assert!(error.to_string().ends_with("jumping from/to each delimiter."));
+	}
+
+	#[test]
+	fn display_without_blank_lines() {
+		let value = r#"
+			[ array
+				{ named
+					key value
+				}
+				{
+					key value
+				}
+				[
+					element
+				]
+			]
+			{ table
+				empty
+			}
+		"#;
+		let acon = value.parse::<Acon>().unwrap();
+		let string = acon.to_string();
+		assert!(!string.contains("\n\n"));
+		assert_eq!(string.parse::<Acon>(), Ok(acon));
+	}
+
+	#[test]
+	fn display_named_table_in_array() {
+		let value = r#"
+			[
+				{ lorem
+					ipsum dolor
+				}
+				{
+					{ sit
+					}
+					amet consectetur
+				}
+			]
+		"#;
+		let acon = value.parse::<Acon>().unwrap();
+		let expected = if cfg!(feature = "preserve_order") {
+			"[\n\t{ lorem\n\t\tipsum dolor\n\t}\n\t{\n\t\t{ sit\n\t\t}\n\t\tamet consectetur\n\t}\n]\n"
+		} else {
+			"[\n\t{ lorem\n\t\tipsum dolor\n\t}\n\t{\n\t\tamet consectetur\n\t\t{ sit\n\t\t}\n\t}\n]\n"
+		};
+		assert_eq!(acon.to_string(), expected);
+	}
+
+	#[cfg(feature = "preserve_order")]
+	#[test]
+	fn preserve_order() {
+		let mut acon = "zebra 1
+{ middle
+}
+apple 2
+".parse::<Acon>().unwrap();
+		assert_eq!(acon.to_string(), "zebra 1
+{ middle
+}
+apple 2
+");
+		acon.set_path("banana", Acon::String("3".to_string())).unwrap();
+		acon.set_path("zebra", Acon::String("4".to_string())).unwrap();
+		acon.remove_path("middle");
+		assert_eq!(acon.to_string(), "zebra 4
+apple 2
+banana 3
+");
+	}
+
+	#[test]
+	fn display_non_table_root() {
+		let array = Acon::Array(vec![Acon::String("a".to_string()), Acon::String("".to_string()),
+		                             Acon::Array(vec![])]);
+		assert_eq!(array.to_string(), "a\n\n[\n]\n");
+		assert_eq!(Acon::String("# x".to_string()).to_string(), "(35) x\n");
+	}
+
+	/// A tree of the shape the parser produces: the root is a table, and strings inside
+	/// tables never have an empty key.
+	#[derive(Clone, Debug)]
+	struct Parsed(Acon);
+
+	impl Arbitrary for Parsed {
+		fn arbitrary(g: &mut Gen) -> Parsed {
+			return Parsed(Acon::Table(table(g, 3)));
+
+			fn node(g: &mut Gen, depth: usize) -> Acon {
+				match if depth == 0 { 0 } else { u8::arbitrary(g) % 3 } {
+					0 => Acon::String(String::arbitrary(g)),
+					1 => Acon::Array((0..usize::arbitrary(g) % 4).map(|_| node(g, depth - 1)).collect()),
+					_ => Acon::Table(table(g, depth - 1)),
+				}
+			}
+
+			fn table(g: &mut Gen, depth: usize) -> Table {
+				let mut table = Table::new();
+				for _ in 0..usize::arbitrary(g) % 4 {
+					let value = node(g, depth);
+					let mut key = String::arbitrary(g);
+					if key.is_empty() && matches!(value, Acon::String(_)) {
+						key.push('k');
+					}
+					table.insert(key, value);
+				}
+				table
+			}
+		}
+	}
+
+	/// Lines built from delimiters and words that the parser treats specially
+	#[derive(Clone, Debug)]
+	struct Text(String);
+
+	impl Arbitrary for Text {
+		fn arbitrary(g: &mut Gen) -> Text {
+			let words = ["{", "}", "[", "]", "$", "#", "", "key", "value", "(32)", "(10)", "(46)",
+			             "(35)", "(40)10)", "a.b", "\t"];
+			let mut text = String::new();
+			for _ in 0..usize::arbitrary(g) % 20 {
+				for _ in 0..usize::arbitrary(g) % 4 {
+					text.push_str(g.choose(&words).unwrap());
+					text.push_str(g.choose(&[" ", "  ", "\t"]).unwrap());
+				}
+				text.push('\n');
+			}
+			Text(text)
+		}
+	}
+
+	#[test]
+	fn quickcheck_round_trip_tree() {
+		fn property(tree: Parsed) -> bool {
+			tree.0.to_string().parse::<Acon>() == Ok(tree.0)
+		}
+		QuickCheck::new().tests(500).quickcheck(property as fn(Parsed) -> bool);
+	}
+
+	#[test]
+	fn quickcheck_round_trip_flat() {
+		fn property(tree: Parsed) -> bool {
+			Acon::unflatten(&tree.0.flatten()) == Ok(tree.0)
+		}
+		QuickCheck::new().tests(500).quickcheck(property as fn(Parsed) -> bool);
+	}
+
+	#[test]
+	fn quickcheck_round_trip_text() {
+		fn property(text: Text) -> bool {
+			match text.0.parse::<Acon>() {
+				Ok(acon) => acon.to_string().parse::<Acon>() == Ok(acon),
+				Err(_) => true,
+			}
+		}
+		QuickCheck::new().tests(2000).quickcheck(property as fn(Text) -> bool);
+	}
+
 	#[bench]
 	fn large_table(bench: &mut Bencher) {
 		use std::fs::File;
@@ -0,0 +1,342 @@
+//! The acon command-line tool
+//!
+//! Reads ACON from a file, or from standard input when there is no file or it is `-`, and
+//! prints the result to standard output. Exits with 0 on success, 1 when the input is
+//! invalid or the path has no value, and 2 when the command line is wrong.
+
+extern crate acon;
+#[cfg(feature = "json")]
+extern crate serde_json;
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+
+use acon::{Acon, AconError, Commented, Document, Path, Segment};
+use std::str::FromStr;
+
+const USAGE: &str = "\
+usage: acon [-i | --in-place] [--infer] [--] <command> [arguments] [file]
+
+commands:
+  get <path> [file]          print the value at the path
+  set <path> <value> [file]  set the string at the path and print the document
+  del <path> [file]          remove the value at the path and print the document
+  keys <path> [file]         print the keys of the table or the indices of the array
+  fmt [file]                 print the document in the canonical format, with its comments
+  validate [file...]         check that the documents parse, printing what is wrong
+  to-json [file]             print the document as JSON
+  from-json [file]           print a JSON object as a document
+
+Paths are dot-separated, and the empty path \"\" is the whole document.
+Options only come before the command, so arguments after it are never taken as options.
+Set and del only change the lines of the value, keeping the rest of the document as it is.
+With --in-place, set, del and fmt write the document back to the file.
+With --infer, to-json writes numbers, booleans and null instead of strings.
+The JSON commands are only available when acon is built with the json feature.
+";
+
+/// Why a command failed, which decides the exit code
+#[derive(Debug)]
+enum Failure {
+	/// The input is invalid or does not have what was asked for
+	Error(String),
+	/// The command line is wrong
+	Usage(String),
+}
+
+fn main() {
+	let args = env::args().skip(1).collect::<Vec<_>>();
+	let stdin = io::stdin();
+	let stdout = io::stdout();
+	let result = run(&args, &mut stdin.lock(), &mut stdout.lock());
+	process::exit(match result {
+		Ok(()) => 0,
+		Err(Failure::Error(message)) => {
+			eprint!("{}", message);
+			1
+		}
+		Err(Failure::Usage(message)) => {
+			eprint!("acon: {}\n\n{}", message, USAGE);
+			2
+		}
+	});
+}
+
+fn run(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<(), Failure> {
+	let mut in_place = false;
+	let mut infer = false;
+	let mut rest = args;
+	while let Some((arg, tail)) = rest.split_first() {
+		match arg.as_str() {
+			"-i" | "--in-place" => in_place = true,
+			"--infer" => infer = true,
+			"-h" | "--help" => return write(stdout, USAGE),
+			"--" => {
+				rest = tail;
+				break;
+			}
+			_ => break,
+		}
+		rest = tail;
+	}
+	let positional = rest.iter().map(String::as_str).collect::<Vec<_>>();
+	let (command, arguments) = match positional.split_first() {
+		Some((command, arguments)) => (*command, arguments),
+		None => return Err(Failure::Usage("no command given".to_string())),
+	};
+	if in_place && !["set", "del", "fmt"].contains(&command) {
+		return Err(Failure::Usage(format!("{} can not edit in place", command)));
+	}
+	if infer && command != "to-json" {
+		return Err(Failure::Usage(format!("{} does not infer scalars", command)));
+	}
+	match command {
+		"get" => {
+			let (path, file) = arguments_with_file(command, arguments, 1)?;
+			let acon = parse(file, stdin)?;
+			match *find(&acon, path[0])? {
+				Acon::String(ref string) => write(stdout, &format!("{}\n", string)),
+				ref value => write(stdout, &value.to_string()),
+			}
+		}
+		"set" => {
+			let (arguments, file) = arguments_with_file(command, arguments, 2)?;
+			let path = to_path(arguments[0]);
+			match path.last() {
+				None => return Err(Failure::Error("a string can not replace the whole document\n".to_string())),
+				Some(Segment::Key(key)) if key.is_empty() => {
+					return Err(Failure::Error(format!("{}: a string needs a key that is not empty\n", arguments[0])));
+				}
+				_ => {}
+			}
+			let mut document = parse::<Document>(file, stdin)?;
+			document.set(path, Acon::String(arguments[1].to_string()))
+				.map_err(|error| Failure::Error(format!("{}\n", error)))?;
+			output(in_place, file, &document.to_string(), stdout)
+		}
+		"del" => {
+			let (path, file) = arguments_with_file(command, arguments, 1)?;
+			let mut document = parse::<Document>(file, stdin)?;
+			if document.remove(to_path(path[0])).is_none() {
+				return Err(Failure::Error(format!("no value at {}\n", path[0])));
+			}
+			output(in_place, file, &document.to_string(), stdout)
+		}
+		"keys" => {
+			let (path, file) = arguments_with_file(command, arguments, 1)?;
+			let acon = parse(file, stdin)?;
+			let keys = match *find(&acon, path[0])? {
+				Acon::Table(ref table) => {
+					table.keys().map(|key| Segment::Key(key.clone()).to_string()).collect::<Vec<_>>()
+				}
+				Acon::Array(ref array) => (0..array.len()).map(|index| index.to_string()).collect(),
+				Acon::String(_) => return Err(Failure::Error(format!("{} is a string\n", path[0]))),
+			};
+			write(stdout, &keys.iter().map(|key| format!("{}\n", key)).collect::<String>())
+		}
+		"fmt" => {
+			let (_, file) = arguments_with_file(command, arguments, 0)?;
+			let commented = parse::<Commented>(file, stdin)?;
+			output(in_place, file, &commented.to_string(), stdout)
+		}
+		"validate" => {
+			let files = if arguments.is_empty() { vec!["-"] } else { arguments.to_vec() };
+			let mut problems = String::new();
+			for file in files {
+				if let Err(Failure::Error(message)) = parse::<Acon>(Some(file), stdin) {
+					problems.push_str(&message);
+				}
+			}
+			if problems.is_empty() { Ok(()) } else { Err(Failure::Error(problems)) }
+		}
+		"to-json" | "from-json" => {
+			let (_, file) = arguments_with_file(command, arguments, 0)?;
+			json(command == "to-json", infer, file, stdin, stdout)
+		}
+		_ => Err(Failure::Usage(format!("unknown command {}", command))),
+	}
+}
+
+/// Split the arguments of a command into those it requires and the optional file
+fn arguments_with_file<'a>(command: &str, arguments: &'a [&'a str],
+                           required: usize) -> Result<(&'a [&'a str], Option<&'a str>), Failure> {
+	match arguments.len() {
+		length if length == required => Ok((arguments, None)),
+		length if length == required + 1 => Ok((&arguments[..required], Some(arguments[required]))),
+		_ => Err(Failure::Usage(format!("wrong number of arguments for {}", command))),
+	}
+}
+
+fn to_path(path: &str) -> Path {
+	if path.is_empty() { Path::new() } else { Path::from(path) }
+}
+
+fn find<'a>(acon: &'a Acon, path: &str) -> Result<&'a Acon, Failure> {
+	acon.path(to_path(path)).ok_or_else(|| Failure::Error(format!("no value at {}\n", path)))
+}
+
+/// Read and parse a file, or standard input if there is no file or it is `-`
+fn parse<T: FromStr<Err = AconError>>(file: Option<&str>, stdin: &mut dyn Read) -> Result<T, Failure> {
+	let (name, input) = read(file, stdin)?;
+	input.parse().map_err(|error: AconError| Failure::Error(format!("{}: {}", name, error.render(&input))))
+}
+
+/// Read a file, or standard input if there is no file or it is `-`. Returns the name to
+/// report errors with, and the contents.
+fn read<'a>(file: Option<&'a str>, stdin: &mut dyn Read) -> Result<(&'a str, String), Failure> {
+	let mut input = String::new();
+	let (name, result) = match file {
+		Some(file) if file != "-" => (file, fs::File::open(file).and_then(|mut file| file.read_to_string(&mut input))),
+		_ => ("<stdin>", stdin.read_to_string(&mut input)),
+	};
+	result.map_err(|error| Failure::Error(format!("{}: {}\n", name, error)))?;
+	Ok((name, input))
+}
+
+/// Convert the document into JSON, or a JSON object into a document
+#[cfg(feature = "json")]
+fn json(to_json: bool, infer: bool, file: Option<&str>, stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<(), Failure> {
+	if to_json {
+		let json = parse::<Acon>(file, stdin)?.to_json(infer);
+		let text = serde_json::to_string_pretty(&json).map_err(|error| Failure::Error(format!("{}\n", error)))?;
+		return write(stdout, &format!("{}\n", text));
+	}
+	let (name, input) = read(file, stdin)?;
+	match serde_json::from_str(&input) {
+		Ok(json @ serde_json::Value::Object(_)) => match Acon::try_from_json(&json) {
+			Ok(acon) => write(stdout, &acon.to_string()),
+			Err(error) => Err(Failure::Error(format!("{}: {}\n", name, error))),
+		},
+		Ok(_) => Err(Failure::Error(format!("{}: the JSON value is not an object\n", name))),
+		Err(error) => Err(Failure::Error(format!("{}: {}\n", name, error))),
+	}
+}
+
+#[cfg(not(feature = "json"))]
+fn json(_: bool, _: bool, _: Option<&str>, _: &mut dyn Read, _: &mut dyn Write) -> Result<(), Failure> {
+	Err(Failure::Error("acon is built without the json feature\n".to_string()))
+}
+
+/// Print the document, or write it back to its file
+fn output(in_place: bool, file: Option<&str>, text: &str, stdout: &mut dyn Write) -> Result<(), Failure> {
+	match file {
+		Some(file) if in_place && file != "-" => {
+			fs::write(file, text).map_err(|error| Failure::Error(format!("{}: {}\n", file, error)))
+		}
+		_ if in_place => Err(Failure::Usage("editing in place needs a file".to_string())),
+		_ => write(stdout, text),
+	}
+}
+
+fn write(stdout: &mut dyn Write, text: &str) -> Result<(), Failure> {
+	stdout.write_all(text.as_bytes()).map_err(|error| Failure::Error(format!("{}\n", error)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const INPUT: &str = "# a site\nname  my   site\n{ server\n    host example.com\n    [ ports\n        80\n    ]\n}\n";
+
+	fn acon(args: &[&str], input: &str) -> Result<String, Failure> {
+		let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+		let mut output = Vec::new();
+		run(&args, &mut input.as_bytes(), &mut output)?;
+		Ok(String::from_utf8(output).unwrap())
+	}
+
+	#[test]
+	fn queries() {
+		assert_eq!(acon(&["get", "server.host"], INPUT).unwrap(), "example.com\n");
+		assert_eq!(acon(&["get", "server.ports"], INPUT).unwrap(), "80\n");
+		assert_eq!(acon(&["get", "name", "-"], INPUT).unwrap(), "my site\n");
+		assert_eq!(acon(&["keys", ""], INPUT).unwrap(), "name\nserver\n");
+		assert_eq!(acon(&["keys", "server.ports"], INPUT).unwrap(), "0\n");
+		assert!(matches!(acon(&["get", "server.missing"], INPUT), Err(Failure::Error(_))));
+		assert!(matches!(acon(&["keys", "name"], INPUT), Err(Failure::Error(_))));
+	}
+
+	#[test]
+	fn edits() {
+		assert_eq!(acon(&["set", "server.ports.+", "443"], INPUT).unwrap(),
+		           "# a site\nname  my   site\n{ server\n    host example.com\n    [ ports\n        80\n        443\n    ]\n}\n");
+		assert_eq!(acon(&["del", "server"], INPUT).unwrap(), "# a site\nname  my   site\n");
+		assert_eq!(acon(&["fmt"], INPUT).unwrap(), "# a site\nname my site\n{ server\n\thost example.com\n\t[ ports\n\t\t80\n\t]\n}\n");
+		assert!(matches!(acon(&["set", "name.x", "y"], INPUT), Err(Failure::Error(_))));
+		assert!(matches!(acon(&["set", "", "hello"], INPUT), Err(Failure::Error(_))));
+		assert!(matches!(acon(&["set", "server.", "v"], INPUT), Err(Failure::Error(_))));
+		assert!(matches!(acon(&["del", "nothing"], INPUT), Err(Failure::Error(_))));
+	}
+
+	#[test]
+	fn comments_are_kept() {
+		let input = "# the site\n{ server\n  # where it runs\n  host example.com\n  # the port\n  port 80\n}\n";
+		assert_eq!(acon(&["set", "server.host", "other.com"], input).unwrap(),
+		           "# the site\n{ server\n  # where it runs\n  host other.com\n  # the port\n  port 80\n}\n");
+		assert_eq!(acon(&["set", "server.tls", "on"], input).unwrap(),
+		           "# the site\n{ server\n  # where it runs\n  host example.com\n  # the port\n  port 80\n  tls on\n}\n");
+		assert_eq!(acon(&["del", "server.host"], input).unwrap(), "# the site\n{ server\n  # where it runs\n  # the port\n  port 80\n}\n");
+		assert_eq!(acon(&["fmt"], input).unwrap(),
+		           "# the site\n{ server\n\t# where it runs\n\thost example.com\n\t# the port\n\tport 80\n}\n");
+	}
+
+	#[test]
+	fn options_before_the_command() {
+		assert_eq!(acon(&["set", "name", "-i"], INPUT).unwrap(), INPUT.replace("name  my   site", "name -i"));
+		assert_eq!(acon(&["set", "--", "--infer"], "").unwrap(), "-- --infer\n");
+		assert!(matches!(acon(&["fmt", "--in-place"], INPUT), Err(Failure::Error(_))));
+		assert!(matches!(acon(&["--", "-i", "fmt"], INPUT), Err(Failure::Usage(_))));
+	}
+
+	#[test]
+	fn in_place() {
+		let file = env::temp_dir().join(format!("acon-in-place-{}.acon", process::id()));
+		let name = file.to_str().unwrap();
+		fs::write(&file, INPUT).unwrap();
+		assert_eq!(acon(&["-i", "set", "name", "other", name], "").unwrap(), "");
+		assert_eq!(acon(&["get", "name", name], "").unwrap(), "other\n");
+		assert!(matches!(acon(&["-i", "set", "", "hello", name], ""), Err(Failure::Error(_))));
+		assert!(matches!(acon(&["-i", "set", "server.", "v", name], ""), Err(Failure::Error(_))));
+		assert_eq!(acon(&["get", "name", name], "").unwrap(), "other\n");
+		assert_eq!(acon(&["--in-place", "--", "fmt", name], "").unwrap(), "");
+		assert_eq!(fs::read_to_string(&file).unwrap(), "# a site\nname other\n{ server\n\thost example.com\n\t[ ports\n\t\t80\n\t]\n}\n");
+		fs::remove_file(&file).unwrap();
+		assert!(matches!(acon(&["-i", "fmt"], INPUT), Err(Failure::Usage(_))));
+		assert!(matches!(acon(&["-i", "get", "name", name], INPUT), Err(Failure::Usage(_))));
+	}
+
+	#[test]
+	fn validate() {
+		assert_eq!(acon(&["validate"], INPUT).unwrap(), "");
+		match acon(&["validate", "-"], "{ a\n]\n") {
+			Err(Failure::Error(message)) => assert!(message.starts_with("<stdin>: error: expected } to close the table\n")),
+			other => panic!("expected an error, got {:?}", other),
+		}
+		assert!(matches!(acon(&["validate", "/nonexistent/file.acon"], ""), Err(Failure::Error(_))));
+	}
+
+	#[cfg(feature = "json")]
+	#[test]
+	fn json() {
+		assert_eq!(acon(&["to-json"], INPUT).unwrap(),
+		           "{\n  \"name\": \"my site\",\n  \"server\": {\n    \"host\": \"example.com\",\n    \"ports\": [\n      \"80\"\n    ]\n  }\n}\n");
+		assert_eq!(acon(&["--infer", "to-json", "-"], "[ ports\n80\n]\n").unwrap(), "{\n  \"ports\": [\n    80\n  ]\n}\n");
+		assert_eq!(acon(&["from-json"], r#"{"name": "my site", "server": {"host": "example.com", "ports": [80]}}"#).unwrap(),
+		           INPUT.parse::<Acon>().unwrap().to_string());
+		assert!(matches!(acon(&["from-json"], "[1, 2]"), Err(Failure::Error(_))));
+		assert!(matches!(acon(&["from-json"], r#"{"": "x", "a": {"": "y"}}"#), Err(Failure::Error(_))));
+		assert!(matches!(acon(&["from-json"], "{"), Err(Failure::Error(_))));
+		assert!(matches!(acon(&["--infer", "from-json"], "{}"), Err(Failure::Usage(_))));
+	}
+
+	#[test]
+	fn usage() {
+		assert!(matches!(acon(&[], INPUT), Err(Failure::Usage(_))));
+		assert!(matches!(acon(&["frobnicate"], INPUT), Err(Failure::Usage(_))));
+		assert!(matches!(acon(&["get"], INPUT), Err(Failure::Usage(_))));
+		assert!(matches!(acon(&["set", "a", "b", "c", "d"], INPUT), Err(Failure::Usage(_))));
+		assert!(acon(&["--help"], "").unwrap().starts_with("usage: acon"));
+	}
+}
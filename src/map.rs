@@ -0,0 +1,322 @@
+//! The map behind tables
+//!
+//! Tables keep their keys sorted, or in the order they are inserted with the `preserve_order`
+//! feature. Both orders are provided by the same type with the same methods, so enabling the
+//! feature in one crate does not break another crate that uses tables.
+
+#[cfg(feature = "preserve_order")]
+use indexmap::{self, IndexMap};
+use std::borrow::Borrow;
+#[cfg(not(feature = "preserve_order"))]
+use std::collections::{btree_map, BTreeMap};
+use std::fmt;
+use std::hash::Hash;
+use std::iter::FromIterator;
+use std::ops;
+
+#[cfg(not(feature = "preserve_order"))]
+type Inner<K, V> = BTreeMap<K, V>;
+#[cfg(feature = "preserve_order")]
+type Inner<K, V> = IndexMap<K, V>;
+
+/// A map of keys to values, which iterates in the order of the keys, or in the order they
+/// were inserted with the `preserve_order` feature
+///
+///  ```
+///  use acon::{Acon, Table};
+///  let mut table = Table::new();
+///  table.insert("b".to_string(), Acon::String("1".to_string()));
+///  table.insert("a".to_string(), Acon::String("2".to_string()));
+///  assert_eq!(table.len(), 2);
+///  assert_eq!(table["a"].string(), "2");
+///  assert!(table.keys().any(|key| key == "b"));
+///  ```
+///
+pub struct Map<K, V> {
+	map: Inner<K, V>,
+}
+
+impl<K, V> Map<K, V> {
+	/// Create an empty map
+	pub fn new() -> Map<K, V> {
+		Map { map: Inner::new() }
+	}
+
+	/// The number of entries
+	pub fn len(&self) -> usize {
+		self.map.len()
+	}
+
+	/// Whether there are no entries
+	pub fn is_empty(&self) -> bool {
+		self.map.is_empty()
+	}
+
+	/// Remove all entries
+	pub fn clear(&mut self) {
+		self.map.clear()
+	}
+
+	/// Iterate over the entries
+	pub fn iter(&self) -> Iter<'_, K, V> {
+		Iter { iter: self.map.iter() }
+	}
+
+	/// Iterate over the entries, with mutable references to the values
+	pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+		IterMut { iter: self.map.iter_mut() }
+	}
+
+	/// Iterate over the keys
+	pub fn keys(&self) -> Keys<'_, K, V> {
+		Keys { iter: self.map.keys() }
+	}
+
+	/// Iterate over the values
+	pub fn values(&self) -> Values<'_, K, V> {
+		Values { iter: self.map.values() }
+	}
+
+	/// Iterate over mutable references to the values
+	pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+		ValuesMut { iter: self.map.values_mut() }
+	}
+}
+
+impl<K: Ord + Hash, V> Map<K, V> {
+	/// Retrieve a reference to the value of a key
+	pub fn get<Q: ?Sized + Ord + Hash>(&self, key: &Q) -> Option<&V> where K: Borrow<Q> {
+		self.map.get(key)
+	}
+
+	/// Retrieve a mutable reference to the value of a key
+	pub fn get_mut<Q: ?Sized + Ord + Hash>(&mut self, key: &Q) -> Option<&mut V> where K: Borrow<Q> {
+		self.map.get_mut(key)
+	}
+
+	/// Whether the key has a value
+	pub fn contains_key<Q: ?Sized + Ord + Hash>(&self, key: &Q) -> bool where K: Borrow<Q> {
+		self.map.contains_key(key)
+	}
+
+	/// Set the value of a key, returning the value it had before. A key that is already
+	/// there keeps its position.
+	pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+		self.map.insert(key, value)
+	}
+
+	/// Remove a key and return its value, keeping the order of the other entries
+	#[cfg(not(feature = "preserve_order"))]
+	pub fn remove<Q: ?Sized + Ord + Hash>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q> {
+		self.map.remove(key)
+	}
+
+	/// Remove a key and return its value, keeping the order of the other entries
+	#[cfg(feature = "preserve_order")]
+	pub fn remove<Q: ?Sized + Ord + Hash>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q> {
+		self.map.shift_remove(key)
+	}
+
+	/// Keep only the entries for which the predicate holds
+	pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F) {
+		self.map.retain(f)
+	}
+
+	/// The entry of a key, for inserting a value only if it is not there yet
+	pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+		Entry { entry: self.map.entry(key) }
+	}
+}
+
+/// An entry of a map, which may or may not have a value
+pub struct Entry<'a, K, V> {
+	#[cfg(not(feature = "preserve_order"))]
+	entry: btree_map::Entry<'a, K, V>,
+	#[cfg(feature = "preserve_order")]
+	entry: indexmap::map::Entry<'a, K, V>,
+}
+
+impl<'a, K: Ord + Hash, V> Entry<'a, K, V> {
+	/// The key of the entry
+	pub fn key(&self) -> &K {
+		self.entry.key()
+	}
+
+	/// The value of the entry, which is set to the default if there is none
+	pub fn or_insert(self, default: V) -> &'a mut V {
+		self.entry.or_insert(default)
+	}
+
+	/// The value of the entry, which is set to the result of the function if there is none
+	pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+		self.entry.or_insert_with(default)
+	}
+}
+
+impl<K, V> Default for Map<K, V> {
+	fn default() -> Map<K, V> {
+		Map::new()
+	}
+}
+
+impl<K: Clone, V: Clone> Clone for Map<K, V> {
+	fn clone(&self) -> Map<K, V> {
+		Map { map: self.map.clone() }
+	}
+}
+
+/// Maps are equal if they have the same entries, in any order
+impl<K: Ord + Hash, V: PartialEq> PartialEq for Map<K, V> {
+	fn eq(&self, other: &Map<K, V>) -> bool {
+		self.map == other.map
+	}
+}
+
+impl<K: Ord + Hash, V: Eq> Eq for Map<K, V> {}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for Map<K, V> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.map.fmt(f)
+	}
+}
+
+impl<K: Ord + Hash + Borrow<Q>, Q: ?Sized + Ord + Hash, V> ops::Index<&Q> for Map<K, V> {
+	type Output = V;
+
+	fn index(&self, key: &Q) -> &V {
+		self.get(key).expect("the key is in the map")
+	}
+}
+
+impl<K: Ord + Hash + Borrow<Q>, Q: ?Sized + Ord + Hash, V> ops::IndexMut<&Q> for Map<K, V> {
+	fn index_mut(&mut self, key: &Q) -> &mut V {
+		self.get_mut(key).expect("the key is in the map")
+	}
+}
+
+impl<K: Ord + Hash, V> FromIterator<(K, V)> for Map<K, V> {
+	fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Map<K, V> {
+		Map { map: Inner::from_iter(iter) }
+	}
+}
+
+impl<K: Ord + Hash, V> Extend<(K, V)> for Map<K, V> {
+	fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+		self.map.extend(iter)
+	}
+}
+
+macro_rules! delegate_iterator {
+	($name:ident, $doc:expr, $item:ty, $btree:ty, $index:ty) => {
+		#[doc = $doc]
+		pub struct $name<'a, K: 'a, V: 'a> {
+			#[cfg(not(feature = "preserve_order"))]
+			iter: $btree,
+			#[cfg(feature = "preserve_order")]
+			iter: $index,
+		}
+
+		impl<'a, K, V> Iterator for $name<'a, K, V> {
+			type Item = $item;
+
+			fn next(&mut self) -> Option<$item> {
+				self.iter.next()
+			}
+
+			fn size_hint(&self) -> (usize, Option<usize>) {
+				self.iter.size_hint()
+			}
+		}
+
+		impl<'a, K, V> DoubleEndedIterator for $name<'a, K, V> {
+			fn next_back(&mut self) -> Option<$item> {
+				self.iter.next_back()
+			}
+		}
+
+		impl<'a, K, V> ExactSizeIterator for $name<'a, K, V> {}
+	};
+}
+
+delegate_iterator!(Iter, "An iterator over the entries of a map", (&'a K, &'a V),
+                   btree_map::Iter<'a, K, V>, indexmap::map::Iter<'a, K, V>);
+delegate_iterator!(IterMut, "An iterator over the entries of a map, with mutable values", (&'a K, &'a mut V),
+                   btree_map::IterMut<'a, K, V>, indexmap::map::IterMut<'a, K, V>);
+delegate_iterator!(Keys, "An iterator over the keys of a map", &'a K,
+                   btree_map::Keys<'a, K, V>, indexmap::map::Keys<'a, K, V>);
+delegate_iterator!(Values, "An iterator over the values of a map", &'a V,
+                   btree_map::Values<'a, K, V>, indexmap::map::Values<'a, K, V>);
+delegate_iterator!(ValuesMut, "An iterator over mutable references to the values of a map", &'a mut V,
+                   btree_map::ValuesMut<'a, K, V>, indexmap::map::ValuesMut<'a, K, V>);
+
+/// An iterator that moves the entries out of a map
+pub struct IntoIter<K, V> {
+	#[cfg(not(feature = "preserve_order"))]
+	iter: btree_map::IntoIter<K, V>,
+	#[cfg(feature = "preserve_order")]
+	iter: indexmap::map::IntoIter<K, V>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+	type Item = (K, V);
+
+	fn next(&mut self) -> Option<(K, V)> {
+		self.iter.next()
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.iter.size_hint()
+	}
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+	fn next_back(&mut self) -> Option<(K, V)> {
+		self.iter.next_back()
+	}
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V> IntoIterator for Map<K, V> {
+	type Item = (K, V);
+	type IntoIter = IntoIter<K, V>;
+
+	fn into_iter(self) -> IntoIter<K, V> {
+		IntoIter { iter: self.map.into_iter() }
+	}
+}
+
+impl<'a, K, V> IntoIterator for &'a Map<K, V> {
+	type Item = (&'a K, &'a V);
+	type IntoIter = Iter<'a, K, V>;
+
+	fn into_iter(self) -> Iter<'a, K, V> {
+		self.iter()
+	}
+}
+
+impl<'a, K, V> IntoIterator for &'a mut Map<K, V> {
+	type Item = (&'a K, &'a mut V);
+	type IntoIter = IterMut<'a, K, V>;
+
+	fn into_iter(self) -> IterMut<'a, K, V> {
+		self.iter_mut()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn removing_keeps_order() {
+		let mut map = vec![("c", 1), ("a", 2), ("b", 3)].into_iter().collect::<Map<_, _>>();
+		assert_eq!(map.remove("a"), Some(2));
+		map.insert("d", 4);
+		let expected = if cfg!(feature = "preserve_order") { ["c", "b", "d"] } else { ["b", "c", "d"] };
+		assert_eq!(map.keys().cloned().collect::<Vec<_>>(), expected);
+		assert_eq!(map, vec![("d", 4), ("b", 3), ("c", 1)].into_iter().collect());
+		*map.entry("b").or_insert(0) += 1;
+		assert_eq!(map["b"], 4);
+	}
+}
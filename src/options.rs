@@ -0,0 +1,363 @@
+//! Configuring the parser
+//!
+//! Parsing with the default options gives the same result as `from_str`. The options change
+//! how the tree is built from the events and limit the resources that parsing may use, so
+//! that untrusted input can be parsed safely. They apply to strings and readers alike.
+
+use std::io::{BufRead, Read};
+use std::str;
+
+use event::State;
+use parse::{Builder, Tree};
+use records::Boundary;
+use {Acon, AconError, AconRef, Event, PullParser, ReadError, Records, Span, Table};
+
+/// What to do when a key appears more than once in the same table
+///
+/// The policy applies to entries, to tables and arrays whether they are closed by a delimiter
+/// or by `$`, and to any combination of them.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum DuplicateKeys {
+	/// Fail with `AconError::OverwritingKey`
+	#[default]
+	Error,
+	/// Keep the last value
+	LastWins,
+	/// Keep the first value and skip the others
+	FirstWins,
+	/// Merge tables key by key, recursively. Anything that is not a pair of tables is
+	/// resolved as `LastWins`.
+	Merge,
+	/// Collect all values of the key into an array, in the order they appear
+	Collect,
+}
+
+/// Options for parsing, built by chaining setters
+///
+/// By default, there are no limits. When the input exceeds a limit, parsing stops with an
+/// error that points at where it happened.
+///
+///  ```
+///  use acon::{DuplicateKeys, ParserOptions};
+///  let input = "level info\nlevel debug\n{ server\n  host a\n}\n{ server\n  port 80\n}\n";
+///  let result = ParserOptions::new().duplicate_keys(DuplicateKeys::Merge).parse(input).unwrap();
+///  assert_eq!(result.path("level").unwrap().string(), "debug");
+///  assert_eq!(result.path("server.host").unwrap().string(), "a");
+///  assert_eq!(result.path("server.port").unwrap().string(), "80");
+///
+///  let result = ParserOptions::new().duplicate_keys(DuplicateKeys::Collect).parse(input).unwrap();
+///  assert_eq!(result.path("level.1").unwrap().string(), "debug");
+///  assert_eq!(result.path("server.1.port").unwrap().string(), "80");
+///
+///  let limited = ParserOptions::new().max_depth(1).max_keys_per_table(100);
+///  assert!(limited.parse("{ a\n\t{ b\n$\n").is_err());
+///  ```
+///
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ParserOptions {
+	pub(crate) duplicate_keys: DuplicateKeys,
+	pub(crate) max_depth: usize,
+	pub(crate) max_keys_per_table: usize,
+	pub(crate) max_array_length: usize,
+	pub(crate) max_total_bytes: usize,
+	pub(crate) max_value_length: usize,
+	pub(crate) max_key_length: usize,
+}
+
+impl Default for ParserOptions {
+	fn default() -> ParserOptions {
+		ParserOptions {
+			duplicate_keys: DuplicateKeys::Error,
+			max_depth: usize::MAX,
+			max_keys_per_table: usize::MAX,
+			max_array_length: usize::MAX,
+			max_total_bytes: usize::MAX,
+			max_value_length: usize::MAX,
+			max_key_length: usize::MAX,
+		}
+	}
+}
+
+impl ParserOptions {
+	/// The default options, which are those of `from_str`
+	pub fn new() -> ParserOptions {
+		ParserOptions::default()
+	}
+
+	/// Set what to do with duplicate keys
+	pub fn duplicate_keys(mut self, policy: DuplicateKeys) -> ParserOptions {
+		self.duplicate_keys = policy;
+		self
+	}
+
+	/// Limit how deep tables and arrays can be nested. The root table is at depth 0, so a
+	/// limit of 0 allows no tables or arrays at all.
+	pub fn max_depth(mut self, limit: usize) -> ParserOptions {
+		self.max_depth = limit;
+		self
+	}
+
+	/// Limit the number of keys in each table, including the root table
+	pub fn max_keys_per_table(mut self, limit: usize) -> ParserOptions {
+		self.max_keys_per_table = limit;
+		self
+	}
+
+	/// Limit the number of elements in each array
+	pub fn max_array_length(mut self, limit: usize) -> ParserOptions {
+		self.max_array_length = limit;
+		self
+	}
+
+	/// Limit the size of the whole input in bytes. A reader is not read past the limit.
+	pub fn max_total_bytes(mut self, limit: usize) -> ParserOptions {
+		self.max_total_bytes = limit;
+		self
+	}
+
+	/// Limit the size of each value in bytes, after unescaping
+	pub fn max_value_length(mut self, limit: usize) -> ParserOptions {
+		self.max_value_length = limit;
+		self
+	}
+
+	/// Limit the size of each key, and of the name of each table and array, in bytes, after
+	/// unescaping
+	pub fn max_key_length(mut self, limit: usize) -> ParserOptions {
+		self.max_key_length = limit;
+		self
+	}
+
+	/// Parse a string
+	pub fn parse(&self, input: &str) -> Result<Acon, AconError> {
+		self.build(input)
+	}
+
+	/// Parse a string into a tree borrowing from it
+	pub fn parse_ref<'a>(&self, input: &'a str) -> Result<AconRef<'a>, AconError> {
+		self.build(input)
+	}
+
+	/// Parse a string into an owned or a borrowed tree
+	fn build<'a, T: Tree<'a>>(&self, input: &'a str) -> Result<T, AconError> {
+		let mut builder = Builder::new(*self);
+		for event in PullParser::with_options(input, *self) {
+			let (span, event) = event?;
+			builder.event(span, event)?;
+		}
+		builder.finish()
+	}
+
+	/// Parse a string without stopping at the first problem, returning the best value that
+	/// can be made of the input along with all problems that were found
+	///
+	/// A closing delimiter without an opening delimiter is skipped, a closing delimiter of
+	/// the wrong kind closes the innermost table or array anyway, the first of duplicate keys
+	/// is kept, and whatever is still open at the end is closed. Exceeding a limit stops
+	/// parsing, and the value holds everything before it.
+	///
+	///  ```
+	///  use acon::{Acon, AconError};
+	///  let input = "}\n{ table\n  key first\n  key second\n]\n[ list\n  item\n";
+	///  let (result, diagnostics) = Acon::parse_lenient(input);
+	///  assert_eq!(result.path("table.key").unwrap().string(), "first");
+	///  assert_eq!(result.path("list.0").unwrap().string(), "item");
+	///  assert_eq!(diagnostics.iter().map(|error| error.span().line).collect::<Vec<_>>(), [1, 4, 5, 6]);
+	///  assert!(matches!(diagnostics[0], AconError::ExcessiveClosingDelimiter(_)));
+	///  ```
+	///
+	pub fn parse_lenient(&self, input: &str) -> (Acon, Vec<AconError>) {
+		let mut state = State::new(*self);
+		let mut builder = Builder::<Acon>::new(*self);
+		let mut diagnostics = Vec::new();
+		for line in input.split_inclusive('\n') {
+			let (span, event) = match state.line(line) {
+				Ok(Some(event)) => event,
+				Ok(None) => continue,
+				// The state has already closed the innermost table or array
+				Err(error @ AconError::WrongClosingDelimiterExpectedArray(..))
+				| Err(error @ AconError::WrongClosingDelimiterExpectedTable(..)) => {
+					let span = error.span();
+					diagnostics.push(error);
+					(span, Event::End)
+				}
+				Err(error @ AconError::ExcessiveClosingDelimiter(_)) => {
+					diagnostics.push(error);
+					continue;
+				}
+				Err(error) => {
+					diagnostics.push(error);
+					break;
+				}
+			};
+			if let Err(error) = builder.lenient_event(span, event, &mut diagnostics) {
+				diagnostics.push(error);
+				break;
+			}
+		}
+		if let Err(error) = state.finish() {
+			diagnostics.push(error);
+		}
+		let _ = builder.lenient_event(Span::default(), Event::CloseAll, &mut diagnostics);
+		let value = builder.finish().unwrap_or_else(|_| Acon::Table(Table::new()));
+		(value, diagnostics)
+	}
+
+	/// Read a record for every entry, table and array at the root of the stream, as
+	/// `Records::new` does. The limit on the total size applies to the whole stream.
+	pub fn records<R: BufRead>(&self, reader: R) -> Records<R> {
+		Records::with_boundary(reader, Boundary::Entry, *self)
+	}
+
+	/// Read a record for every `$` line, as `Records::dollar_terminated` does. The limit on
+	/// the total size applies to the whole stream.
+	pub fn dollar_terminated_records<R: BufRead>(&self, reader: R) -> Records<R> {
+		Records::with_boundary(reader, Boundary::Dollar, *self)
+	}
+
+	/// Parse from a reader one line at a time, as `Acon::from_reader` does
+	pub fn read<R: BufRead>(&self, mut reader: R) -> Result<Acon, ReadError> {
+		let mut state = State::new(*self);
+		let mut builder = Builder::<Acon>::new(*self);
+		let mut buffer = Vec::new();
+		let mut total = 0;
+		loop {
+			buffer.clear();
+			// Read at most one byte past the limit, so that a long line is never read whole
+			let remaining = self.max_total_bytes.saturating_sub(total).saturating_add(1);
+			let read = (&mut reader).take(remaining as u64).read_until(b'\n', &mut buffer)?;
+			if read == 0 {
+				break;
+			}
+			total += read;
+			if total > self.max_total_bytes {
+				return Err(ReadError::Parse(state.excessive_input(&buffer)));
+			}
+			match str::from_utf8(&buffer) {
+				Ok(line) => {
+					if let Some((span, event)) = state.line(line)? {
+						builder.event(span, event.into_owned())?;
+					}
+				}
+				Err(error) => return Err(ReadError::InvalidUtf8(state.invalid_utf8(&buffer, error))),
+			}
+		}
+		state.finish()?;
+		Ok(builder.finish()?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	const INPUT: &str = "key a\n{ t\n\tx 1\n\t{ deep\n\t\ty 2\n\t}\n}\nkey b\n[ list\n\tone\n]\n\
+	                     { t\n\tx 3\n\t{ deep\n\t\tz 4\n$\n[ list\n\ttwo\n]\nkey c\n";
+
+	fn parse(policy: DuplicateKeys) -> Result<Acon, AconError> {
+		let options = ParserOptions::new().duplicate_keys(policy);
+		let result = options.parse(INPUT);
+		assert_eq!(options.read(Cursor::new(INPUT)).map_err(|error| match error {
+			ReadError::Parse(error) => error,
+			other => panic!("expected a parse error, got {:?}", other),
+		}), result);
+		result
+	}
+
+	#[test]
+	fn error_by_default() {
+		assert_eq!(parse(DuplicateKeys::Error), INPUT.parse::<Acon>());
+		assert!(matches!(parse(DuplicateKeys::Error), Err(AconError::OverwritingKey(span, None)) if span.line == 8));
+	}
+
+	#[test]
+	fn first_and_last_wins() {
+		let first = parse(DuplicateKeys::FirstWins).unwrap();
+		assert_eq!(first, "key a\n{ t\n\tx 1\n\t{ deep\n\t\ty 2\n$\n[ list\n\tone\n]\n".parse().unwrap());
+		let last = parse(DuplicateKeys::LastWins).unwrap();
+		assert_eq!(last, "key c\n{ t\n\tx 3\n\t{ deep\n\t\tz 4\n$\n[ list\n\ttwo\n]\n".parse().unwrap());
+	}
+
+	#[test]
+	fn merge() {
+		let merged = parse(DuplicateKeys::Merge).unwrap();
+		assert_eq!(merged, "key c\n{ t\n\tx 3\n\t{ deep\n\t\ty 2\n\t\tz 4\n$\n[ list\n\ttwo\n]\n".parse().unwrap());
+	}
+
+	#[test]
+	fn collect() {
+		let collected = parse(DuplicateKeys::Collect).unwrap();
+		let expected = "[ key\n\ta\n\tb\n\tc\n]\n[ t\n\t{\n\t\tx 1\n\t\t{ deep\n\t\t\ty 2\n\t\t}\n\t}\n\t{\n\t\tx 3\n\t\t{ deep\n\t\t\tz 4\n\t\t}\n\t}\n]\n\
+		                [ list\n\t[\n\t\tone\n\t]\n\t[\n\t\ttwo\n\t]\n]\n";
+		assert_eq!(collected, expected.parse().unwrap());
+	}
+
+	fn limited(options: ParserOptions, input: &str) -> Result<Acon, AconError> {
+		let result = options.parse(input);
+		match options.read(Cursor::new(input)) {
+			Err(ReadError::Parse(error)) => assert_eq!(Err(error), result),
+			Ok(acon) => assert_eq!(Ok(acon), result),
+			Err(other) => panic!("expected a parse error, got {:?}", other),
+		}
+		result
+	}
+
+	#[test]
+	fn limits() {
+		let input = "key value
+{ a
+	[ b
+		one
+		two
+	]
+}
+";
+		assert!(limited(ParserOptions::new().max_depth(2).max_keys_per_table(2).max_array_length(2)
+		                .max_total_bytes(input.len()).max_value_length(5), input).is_ok());
+
+		let error = limited(ParserOptions::new().max_depth(1), input).unwrap_err();
+		assert_eq!(error, AconError::ExcessiveDepth(Span { line: 3, column: 2, start: 15, end: 16 }, 1));
+		let error = limited(ParserOptions::new().max_keys_per_table(1), input).unwrap_err();
+		assert_eq!(error, AconError::ExcessiveKeys(Span { line: 7, column: 1, start: 34, end: 35 }, 1));
+		let error = limited(ParserOptions::new().max_array_length(1), input).unwrap_err();
+		assert_eq!(error, AconError::ExcessiveArrayLength(Span { line: 5, column: 3, start: 27, end: 30 }, 1));
+		let error = limited(ParserOptions::new().max_total_bytes(20), input).unwrap_err();
+		assert_eq!(error, AconError::ExcessiveInput(Span { line: 4, column: 2, start: 20, end: 21 }, 20));
+		let error = limited(ParserOptions::new().max_value_length(4), input).unwrap_err();
+		assert_eq!(error, AconError::ExcessiveValueLength(Span { line: 1, column: 5, start: 4, end: 9 }, 4));
+		let error = limited(ParserOptions::new().max_total_bytes(2), "é\u{e9}").unwrap_err();
+		assert_eq!(error, AconError::ExcessiveInput(Span { line: 1, column: 2, start: 2, end: 3 }, 2));
+		let error = limited(ParserOptions::new().max_value_length(4), "key a(32)b(32)c\n").unwrap_err();
+		assert!(matches!(error, AconError::ExcessiveValueLength(..)));
+
+		let error = limited(ParserOptions::new().max_key_length(3).max_value_length(3), "a(32)b 1\nabcd 1\n").unwrap_err();
+		assert_eq!(error, AconError::ExcessiveKeyLength(Span { line: 2, column: 1, start: 9, end: 13 }, 3));
+		let error = limited(ParserOptions::new().max_key_length(3), "{ abc\n\t[ (97)bcd\n").unwrap_err();
+		assert_eq!(error, AconError::ExcessiveKeyLength(Span { line: 2, column: 4, start: 9, end: 16 }, 3));
+		assert!(limited(ParserOptions::new().max_key_length(3), "[ abc\n\tlong element\n]\n").is_ok());
+
+		let merged = "{ t\n\ta 1\n\tb 2\n}\n{ t\n\tc 3\n\td 4\n}\n{ t\n\te 5\n\tf 6\n}\n";
+		let options = ParserOptions::new().max_keys_per_table(2).duplicate_keys(DuplicateKeys::Merge);
+		let error = limited(options, merged).unwrap_err();
+		assert_eq!(error, AconError::ExcessiveKeys(Span { line: 8, column: 1, start: 30, end: 31 }, 2));
+		assert_eq!(limited(options.max_keys_per_table(6), merged).unwrap().path("t").unwrap().table().len(), 6);
+	}
+
+	#[test]
+	fn lenient() {
+		let (result, diagnostics) = Acon::parse_lenient("a 1\n{ t\n\tx 1\n}\n{ t\n\tx 2\n\t{ u\n$\n{ t\n\t[ v\n\t}\n}\na 2\nb 3\n");
+		assert_eq!(result, "a 1\n{ t\n\tx 1\n}\nb 3\n".parse().unwrap());
+		let found = diagnostics.iter().map(|error| (error.span().line, error.reason())).collect::<Vec<_>>();
+		assert!(matches!(diagnostics[..], [AconError::OverwritingKey(..), AconError::WrongClosingDelimiterExpectedArray(..),
+		                                   AconError::OverwritingKey(..), AconError::OverwritingKey(..)]), "{:?}", found);
+		assert_eq!(found.iter().map(|found| found.0).collect::<Vec<_>>(), [8, 11, 12, 13]);
+
+		let (result, diagnostics) = ParserOptions::new().max_keys_per_table(2).parse_lenient("a 1\n{ t\n\tb 2\n\tc 3\n\td 4\n\te 5\n");
+		assert_eq!(result, "a 1\n{ t\n\tb 2\n\tc 3\n}\n".parse().unwrap());
+		assert!(matches!(diagnostics[..], [AconError::ExcessiveKeys(span, 2), AconError::MultipleTopNodes(_)] if span.line == 5));
+
+		let input = "{ a\n\tkey value\n}\n";
+		assert_eq!(Acon::parse_lenient(input), (input.parse().unwrap(), vec![]));
+	}
+}
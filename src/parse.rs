@@ -0,0 +1,413 @@
+//! Building a tree from events
+//!
+//! The builder keeps a stack of the tables and arrays that are open. Every event either opens
+//! or closes them, or adds an entry to the innermost one. The events come from a string or
+//! from a reader. Duplicate keys are resolved as the parser options say.
+//!
+//! The tree is either borrowed from the input or owned, so that parsing into `Acon` does not
+//! build a borrowed tree only to copy it.
+
+use std::borrow::{Borrow, Cow};
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::io::BufRead;
+use std::mem;
+
+use {Acon, AconError, AconRef, DuplicateKeys, Event, Kind, Map, ParserOptions, ReadError, Span};
+
+/// A tree that the builder can build
+pub(crate) trait Tree<'a>: Sized {
+	/// The keys of tables
+	type Key: Ord + Hash + Borrow<str>;
+
+	fn key(text: Cow<'a, str>) -> Self::Key;
+	fn string(text: Cow<'a, str>) -> Self;
+	fn table(table: Map<Self::Key, Self>) -> Self;
+	fn array(array: Vec<Self>) -> Self;
+	fn kind(&self) -> Kind;
+	fn as_mut(&mut self) -> ValueMut<'_, Self::Key, Self>;
+	/// The table, or the value itself if it is not a table
+	fn into_table(self) -> Result<Map<Self::Key, Self>, Self>;
+}
+
+/// A mutable view of a value of a tree
+pub(crate) enum ValueMut<'v, K, T> {
+	Array(&'v mut Vec<T>),
+	String,
+	Table(&'v mut Map<K, T>),
+}
+
+impl<'a> Tree<'a> for AconRef<'a> {
+	type Key = Cow<'a, str>;
+
+	fn key(text: Cow<'a, str>) -> Cow<'a, str> {
+		text
+	}
+
+	fn string(text: Cow<'a, str>) -> AconRef<'a> {
+		AconRef::String(text)
+	}
+
+	fn table(table: Map<Cow<'a, str>, AconRef<'a>>) -> AconRef<'a> {
+		AconRef::Table(table)
+	}
+
+	fn array(array: Vec<AconRef<'a>>) -> AconRef<'a> {
+		AconRef::Array(array)
+	}
+
+	fn kind(&self) -> Kind {
+		AconRef::kind(self)
+	}
+
+	fn as_mut(&mut self) -> ValueMut<'_, Cow<'a, str>, AconRef<'a>> {
+		match *self {
+			AconRef::Array(ref mut array) => ValueMut::Array(array),
+			AconRef::String(_) => ValueMut::String,
+			AconRef::Table(ref mut table) => ValueMut::Table(table),
+		}
+	}
+
+	fn into_table(self) -> Result<Map<Cow<'a, str>, AconRef<'a>>, AconRef<'a>> {
+		match self {
+			AconRef::Table(table) => Ok(table),
+			value => Err(value),
+		}
+	}
+}
+
+impl<'a> Tree<'a> for Acon {
+	type Key = String;
+
+	fn key(text: Cow<'a, str>) -> String {
+		text.into_owned()
+	}
+
+	fn string(text: Cow<'a, str>) -> Acon {
+		Acon::String(text.into_owned())
+	}
+
+	fn table(table: Map<String, Acon>) -> Acon {
+		Acon::Table(table)
+	}
+
+	fn array(array: Vec<Acon>) -> Acon {
+		Acon::Array(array)
+	}
+
+	fn kind(&self) -> Kind {
+		Acon::kind(self)
+	}
+
+	fn as_mut(&mut self) -> ValueMut<'_, String, Acon> {
+		match *self {
+			Acon::Array(ref mut array) => ValueMut::Array(array),
+			Acon::String(_) => ValueMut::String,
+			Acon::Table(ref mut table) => ValueMut::Table(table),
+		}
+	}
+
+	fn into_table(self) -> Result<Map<String, Acon>, Acon> {
+		match self {
+			Acon::Table(table) => Ok(table),
+			value => Err(value),
+		}
+	}
+}
+
+struct Node<'a, T: Tree<'a>> {
+	name: Cow<'a, str>,
+	value: T,
+	opened: Span,
+	/// The keys of the table whose values are collected into an array
+	collected: HashSet<T::Key>,
+}
+
+pub(crate) struct Builder<'a, T: Tree<'a>> {
+	stack: Vec<Node<'a, T>>,
+	options: ParserOptions,
+}
+
+impl<'a, T: Tree<'a>> Builder<'a, T> {
+	pub(crate) fn new(options: ParserOptions) -> Builder<'a, T> {
+		let mut stack = vec![];
+		push_base_table(&mut stack);
+		Builder { stack, options }
+	}
+
+	/// Add an event that was found at the span
+	pub(crate) fn event(&mut self, span: Span, event: Event<'a>) -> Result<(), AconError> {
+		let options = &self.options;
+		let stack = &mut self.stack;
+		match event {
+			Event::TableStart(name) => push_node(name, T::table(Map::new()), stack, span),
+			Event::ArrayStart(name) => push_node(name, T::array(Vec::new()), stack, span),
+			Event::End => close_array_or_table(stack, span, options)?,
+			Event::CloseAll => close_all_nestings(stack, span, options)?,
+			Event::Comment(_) => {}
+			Event::ArrayItem(value) => {
+				match stack.last_mut().map(|node| node.value.as_mut()) {
+					Some(ValueMut::Array(array)) => push_element(array, T::string(value), span, options)?,
+					Some(_) => return Err(AconError::InternalStringTop(span)),
+					None => return Err(AconError::MissingStackTop(span)),
+				}
+			}
+			Event::Entry(key, value) => {
+				match stack.last_mut().map(|node| (node.value.as_mut(), &mut node.collected)) {
+					Some((ValueMut::Table(table), collected)) => {
+						insert_entry(table, collected, T::key(key), T::string(value), options, span, None)?;
+					}
+					Some(_) => return Err(AconError::InternalStringTop(span)),
+					None => return Err(AconError::MissingStackTop(span)),
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Add an event, reporting duplicate keys instead of failing on them so that the first of
+	/// them is kept. A `$` closes everything even if some of it can not be added.
+	pub(crate) fn lenient_event(&mut self, span: Span, event: Event<'a>,
+	                            diagnostics: &mut Vec<AconError>) -> Result<(), AconError> {
+		let close_all = event == Event::CloseAll;
+		let mut result = self.event(span, event);
+		while let Err(error) = result {
+			if close_all && self.stack.len() > 1 {
+				diagnostics.push(error);
+				result = self.event(span, Event::CloseAll);
+			} else if let AconError::OverwritingKey(..) = error {
+				diagnostics.push(error);
+				result = Ok(());
+			} else {
+				return Err(error);
+			}
+		}
+		Ok(())
+	}
+
+	/// The value of a key of the root table
+	pub(crate) fn root_entry(&mut self, key: &str) -> Option<&T> {
+		match self.stack[0].value.as_mut() {
+			ValueMut::Table(table) => table.get(key),
+			_ => None,
+		}
+	}
+
+	pub(crate) fn finish(mut self) -> Result<T, AconError> {
+		if let Some(node) = self.stack.pop() {
+			match node.value.kind() {
+				Kind::Array => Err(AconError::TopNodeIsArray(node.opened)),
+				Kind::String => Err(AconError::InternalStringTop(node.opened)),
+				Kind::Table => {
+					if !self.stack.is_empty() {
+						Err(AconError::MultipleTopNodes(node.opened))
+					} else {
+						Ok(node.value)
+					}
+				}
+			}
+		} else {
+			Err(AconError::MissingStackTop(Span::default()))
+		}
+	}
+}
+
+impl Acon {
+	/// Parse ACON from a reader one line at a time, without reading the whole input into memory
+	///
+	/// The result is the same as parsing the whole input as a string.
+	///
+	///  ```
+	///  use acon::Acon;
+	///  let input = std::io::Cursor::new("{ table\n  key value\n$\n");
+	///  let result = Acon::from_reader(input).unwrap();
+	///  assert_eq!(result.path("table.key").unwrap().string(), "value");
+	///  ```
+	///
+	pub fn from_reader<R: BufRead>(reader: R) -> Result<Acon, ReadError> {
+		ParserOptions::new().read(reader)
+	}
+
+	/// Parse ACON without stopping at the first problem, as `ParserOptions::parse_lenient`
+	/// does, returning the value along with every problem that was found
+	pub fn parse_lenient(input: &str) -> (Acon, Vec<AconError>) {
+		ParserOptions::new().parse_lenient(input)
+	}
+}
+
+fn push_base_table<'a, T: Tree<'a>>(stack: &mut Vec<Node<'a, T>>) {
+	push_node(Cow::Borrowed(""), T::table(Map::new()), stack, Span::default());
+}
+
+fn push_node<'a, T: Tree<'a>>(name: Cow<'a, str>, value: T, stack: &mut Vec<Node<'a, T>>, opened: Span) {
+	stack.push(Node { name, value, opened, collected: HashSet::new() });
+}
+
+fn close_all_nestings<'a, T: Tree<'a>>(stack: &mut Vec<Node<'a, T>>, span: Span,
+                                       options: &ParserOptions) -> Result<(), AconError> {
+	while stack.len() > 1 {
+		if let Some(top) = stack.pop() {
+			append_node_to_top(top, stack, span, options)?;
+		}
+	}
+	Ok(())
+}
+
+fn close_array_or_table<'a, T: Tree<'a>>(stack: &mut Vec<Node<'a, T>>, span: Span,
+                                         options: &ParserOptions) -> Result<(), AconError> {
+	if stack.len() == 1 {
+		return Err(AconError::ExcessiveClosingDelimiter(span));
+	}
+	if let Some(top) = stack.pop() {
+		append_node_to_top(top, stack, span, options)
+	} else {
+		Err(AconError::MissingStackTop(span))
+	}
+}
+
+fn append_node_to_top<'a, T: Tree<'a>>(top: Node<'a, T>, stack: &mut [Node<'a, T>], span: Span,
+                                       options: &ParserOptions) -> Result<(), AconError> {
+	if let Some(node) = stack.last_mut() {
+		match node.value.as_mut() {
+			ValueMut::Array(array) => {
+				if top.name.is_empty() {
+					push_element(array, top.value, span, options)?;
+				} else {
+					let mut new = Map::new();
+					new.insert(T::key(top.name), top.value);
+					push_element(array, T::table(new), span, options)?;
+				}
+			}
+			ValueMut::String => { return Err(AconError::InternalStringTop(span)); }
+			ValueMut::Table(table) => {
+				insert_entry(table, &mut node.collected, T::key(top.name), top.value, options, span, Some(top.opened))?;
+			}
+		}
+		Ok(())
+	} else {
+		Err(AconError::ExcessiveClosingDelimiter(span))
+	}
+}
+
+fn push_element<T>(array: &mut Vec<T>, value: T, span: Span, options: &ParserOptions) -> Result<(), AconError> {
+	if array.len() >= options.max_array_length {
+		return Err(AconError::ExcessiveArrayLength(span, options.max_array_length));
+	}
+	array.push(value);
+	Ok(())
+}
+
+/// Insert an entry into a table, resolving a duplicate key by the policy of the options
+fn insert_entry<'a, T: Tree<'a>>(table: &mut Map<T::Key, T>, collected: &mut HashSet<T::Key>, key: T::Key,
+                                 value: T, options: &ParserOptions, span: Span,
+                                 opened: Option<Span>) -> Result<(), AconError> {
+	let name: &str = key.borrow();
+	if !table.contains_key(name) && table.len() >= options.max_keys_per_table {
+		return Err(AconError::ExcessiveKeys(span, options.max_keys_per_table));
+	}
+	let existing = match table.get_mut(name) {
+		Some(existing) => existing,
+		None => {
+			table.insert(key, value);
+			return Ok(());
+		}
+	};
+	match options.duplicate_keys {
+		DuplicateKeys::Error => return Err(AconError::OverwritingKey(span, opened)),
+		DuplicateKeys::LastWins => *existing = value,
+		DuplicateKeys::FirstWins => {}
+		DuplicateKeys::Merge => merge(existing, value, options, span)?,
+		DuplicateKeys::Collect => {
+			if collected.contains(name) {
+				if let ValueMut::Array(array) = existing.as_mut() {
+					push_element(array, value, span, options)?;
+				}
+			} else if options.max_array_length < 2 {
+				return Err(AconError::ExcessiveArrayLength(span, options.max_array_length));
+			} else {
+				let first = mem::replace(existing, T::array(Vec::new()));
+				*existing = T::array(vec![first, value]);
+				collected.insert(key);
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Merge a table into a table key by key. Anything else replaces the existing value. The
+/// merged table is held to the limit of keys, as if its keys had been written in it.
+fn merge<'a, T: Tree<'a>>(existing: &mut T, value: T, options: &ParserOptions, span: Span) -> Result<(), AconError> {
+	let new = match value.into_table() {
+		Ok(new) => new,
+		Err(value) => {
+			*existing = value;
+			return Ok(());
+		}
+	};
+	match existing.as_mut() {
+		ValueMut::Table(table) => {
+			for (key, value) in new {
+				let name: &str = key.borrow();
+				match table.get_mut(name) {
+					Some(existing) => merge(existing, value, options, span)?,
+					None => {
+						if table.len() >= options.max_keys_per_table {
+							return Err(AconError::ExcessiveKeys(span, options.max_keys_per_table));
+						}
+						table.insert(key, value);
+					}
+				}
+			}
+		}
+		_ => *existing = T::table(new),
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::{self, Cursor, Read};
+
+	#[test]
+	fn reader_matches_string() {
+		let input = "key value\r\n{ table\n\t[ array\n\t\tone\n\n$ trailing\n# comment\nlast (32)word";
+		let from_reader = Acon::from_reader(Cursor::new(input)).unwrap();
+		assert_eq!(from_reader, input.parse::<Acon>().unwrap());
+		assert_eq!(from_reader.path("table.array.1").unwrap().string(), "");
+	}
+
+	#[test]
+	fn reader_errors_have_spans() {
+		let input = "{ a\n  key value\n  key value\n}\n";
+		match Acon::from_reader(Cursor::new(input)) {
+			Err(ReadError::Parse(error)) => assert_eq!(error, input.parse::<Acon>().unwrap_err()),
+			other => panic!("expected a parse error, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn invalid_utf8() {
+		let input = b"key value\nk\xc3\xa9y v\xffalue\n";
+		match Acon::from_reader(&input[..]) {
+			Err(ReadError::InvalidUtf8(span)) => {
+				assert_eq!(span, Span { line: 2, column: 6, start: 16, end: 17 });
+			}
+			other => panic!("expected invalid UTF-8, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn io_error() {
+		struct Failing;
+		impl Read for Failing {
+			fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+				Err(io::Error::other("disk on fire"))
+			}
+		}
+		match Acon::from_reader(io::BufReader::new(Failing)) {
+			Err(ReadError::Io(error)) => assert_eq!(error.to_string(), "disk on fire"),
+			other => panic!("expected an I/O error, got {:?}", other),
+		}
+	}
+}
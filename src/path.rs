@@ -0,0 +1,310 @@
+//! Structured paths into ACON values
+//!
+//! A path is written as dot-separated segments. Each segment is unescaped, so a key containing
+//! a dot is written with `(46)`. A segment consisting of digits is an index, and the segment `+`
+//! appends to an array.
+
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::fmt;
+use std::slice;
+use std::str::FromStr;
+
+use {escape, unescape, Acon};
+
+/// A single step of a path
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+pub enum Segment {
+	/// The key of a table entry
+	Key(String),
+	/// The index of an array element. In a table, this is the key consisting of the number.
+	Index(usize),
+	/// A new element at the end of an array. This only has meaning when modifying a value.
+	/// In a table, this is the key `+`.
+	Append,
+}
+
+impl Segment {
+	pub(crate) fn parse(string: &str) -> Segment {
+		if string == "+" {
+			return Segment::Append;
+		}
+		match index(string) {
+			Some(index) => Segment::Index(index),
+			None => Segment::Key(unescape(string)),
+		}
+	}
+
+	/// The key that the segment names in a table
+	pub(crate) fn key(&self) -> Cow<'_, str> {
+		match *self {
+			Segment::Key(ref key) => Cow::Borrowed(key),
+			Segment::Index(index) => Cow::Owned(index.to_string()),
+			Segment::Append => Cow::Borrowed("+"),
+		}
+	}
+}
+
+/// Parse a number without superfluous leading zeros, so that the number names the same key
+/// when it is written back.
+fn index(string: &str) -> Option<usize> {
+	if string.is_empty() || !string.bytes().all(|byte| byte.is_ascii_digit())
+		|| (string.len() > 1 && string.starts_with('0')) {
+		return None;
+	}
+	string.parse::<usize>().ok()
+}
+
+impl fmt::Display for Segment {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Segment::Key(ref key) => {
+				let escaped = escape(key);
+				// Keep keys that read as an index or as + from being parsed as such
+				match escaped.chars().next() {
+					Some(first) if index(&escaped).is_some() || escaped == "+" => {
+						write!(f, "({}){}", first as u32, &escaped[first.len_utf8()..])
+					}
+					_ => f.write_str(&escaped),
+				}
+			}
+			Segment::Index(index) => write!(f, "{}", index),
+			Segment::Append => f.write_str("+"),
+		}
+	}
+}
+
+impl<'a> From<&'a str> for Segment {
+	fn from(key: &'a str) -> Segment {
+		Segment::Key(key.to_string())
+	}
+}
+
+impl From<String> for Segment {
+	fn from(key: String) -> Segment {
+		Segment::Key(key)
+	}
+}
+
+impl From<usize> for Segment {
+	fn from(index: usize) -> Segment {
+		Segment::Index(index)
+	}
+}
+
+/// A path to a value inside of another value
+///
+///  ```
+///  use acon::{Acon, Path, Segment};
+///  let mut path = Path::new();
+///  path.push("servers");
+///  path.push(0);
+///  path.push("host.name");
+///  assert_eq!(path.to_string(), "servers.0.host(46)name");
+///  assert_eq!("servers.0.host(46)name".parse::<Path>().unwrap(), path);
+///
+///  let result = "[ servers\n{\nhost(46)name example.com\n}\n]".parse::<Acon>().unwrap();
+///  assert_eq!(result.path(&path).unwrap().string(), "example.com");
+///  assert_eq!(path.pop(), Some(Segment::Key("host.name".to_string())));
+///  ```
+///
+/// Parsing a string always yields at least one segment, since the empty string is the key of
+/// an unnamed table. The root path has no segments, and is created by `Path::new`.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug, Default)]
+pub struct Path {
+	segments: Vec<Segment>,
+}
+
+impl Path {
+	/// Create the path of the root, which has no segments
+	pub fn new() -> Path {
+		Path::default()
+	}
+
+	/// Append a segment to the path
+	pub fn push<S: Into<Segment>>(&mut self, segment: S) {
+		self.segments.push(segment.into());
+	}
+
+	/// Remove the last segment of the path
+	pub fn pop(&mut self) -> Option<Segment> {
+		self.segments.pop()
+	}
+
+	/// Create a new path by appending a segment to this path
+	pub fn join<S: Into<Segment>>(&self, segment: S) -> Path {
+		let mut path = self.clone();
+		path.push(segment);
+		path
+	}
+
+	/// The last segment of the path
+	pub fn last(&self) -> Option<&Segment> {
+		self.segments.last()
+	}
+
+	/// Iterate over the segments of the path
+	pub fn iter(&self) -> slice::Iter<'_, Segment> {
+		self.segments.iter()
+	}
+
+	/// The number of segments in the path
+	pub fn len(&self) -> usize {
+		self.segments.len()
+	}
+
+	/// Check if this is the path of the root
+	pub fn is_empty(&self) -> bool {
+		self.segments.is_empty()
+	}
+}
+
+impl FromStr for Path {
+	type Err = Infallible;
+
+	fn from_str(string: &str) -> Result<Path, Infallible> {
+		Ok(string.split('.').map(Segment::parse).collect())
+	}
+}
+
+impl fmt::Display for Path {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for (index, segment) in self.segments.iter().enumerate() {
+			if index > 0 {
+				f.write_str(".")?;
+			}
+			segment.fmt(f)?;
+		}
+		Ok(())
+	}
+}
+
+impl<'a> From<&'a str> for Path {
+	fn from(string: &'a str) -> Path {
+		string.split('.').map(Segment::parse).collect()
+	}
+}
+
+impl<'a> From<&'a String> for Path {
+	fn from(string: &'a String) -> Path {
+		Path::from(string.as_str())
+	}
+}
+
+impl From<String> for Path {
+	fn from(string: String) -> Path {
+		Path::from(string.as_str())
+	}
+}
+
+impl<'a> From<&'a Path> for Path {
+	fn from(path: &'a Path) -> Path {
+		path.clone()
+	}
+}
+
+impl From<Vec<Segment>> for Path {
+	fn from(segments: Vec<Segment>) -> Path {
+		Path { segments }
+	}
+}
+
+impl std::iter::FromIterator<Segment> for Path {
+	fn from_iter<I: IntoIterator<Item = Segment>>(iterator: I) -> Path {
+		Path { segments: iterator.into_iter().collect() }
+	}
+}
+
+impl<'a> IntoIterator for &'a Path {
+	type Item = &'a Segment;
+	type IntoIter = slice::Iter<'a, Segment>;
+
+	fn into_iter(self) -> slice::Iter<'a, Segment> {
+		self.segments.iter()
+	}
+}
+
+impl Acon {
+	/// Retrieve a reference to the entry named by a single segment
+	pub fn get_segment(&self, segment: &Segment) -> Option<&Acon> {
+		match *segment {
+			Segment::Key(ref key) => self.get(key),
+			_ => match *self {
+				Acon::Array(ref array) => match *segment {
+					Segment::Index(index) => array.get(index),
+					_ => None,
+				},
+				Acon::Table(ref table) => table.get(segment.key().as_ref()),
+				Acon::String(_) => None,
+			},
+		}
+	}
+
+	/// Retrieve a mutable reference to the entry named by a single segment
+	pub fn get_segment_mut(&mut self, segment: &Segment) -> Option<&mut Acon> {
+		match *segment {
+			Segment::Key(ref key) => self.get_mut(key),
+			_ => match *self {
+				Acon::Array(ref mut array) => match *segment {
+					Segment::Index(index) => array.get_mut(index),
+					_ => None,
+				},
+				Acon::Table(ref mut table) => table.get_mut(segment.key().as_ref()),
+				Acon::String(_) => None,
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_segments() {
+		let path = "a.0.+.(48).007.b(46)c..".parse::<Path>().unwrap();
+		assert_eq!(path.iter().cloned().collect::<Vec<_>>(), vec![
+			Segment::Key("a".to_string()), Segment::Index(0), Segment::Append,
+			Segment::Key("0".to_string()), Segment::Key("007".to_string()),
+			Segment::Key("b.c".to_string()), Segment::Key("".to_string()), Segment::Key("".to_string()),
+		]);
+		assert_eq!(path.to_string(), "a.0.+.(48).007.b(46)c..");
+		assert_eq!(path.to_string().parse::<Path>().unwrap(), path);
+		assert_eq!("".parse::<Path>().unwrap(), Path::from(vec![Segment::Key("".to_string())]));
+	}
+
+	#[test]
+	fn display_escapes() {
+		let path: Path = vec![Segment::from("a key"), Segment::from("+"), Segment::from("12"),
+		                      Segment::from(12), Segment::from("f(32)")].into();
+		assert_eq!(path.to_string(), "a(32)key.(43).(49)2.12.f(40)32)");
+		assert_eq!(Path::from(path.to_string()), path);
+	}
+
+	#[test]
+	fn index_and_key_lookup() {
+		let acon = "{ 0\n  1 one\n}\n[ array\n  zero\n]".parse::<Acon>().unwrap();
+		assert_eq!(acon.path("0.1").unwrap().string(), "one");
+		assert_eq!(acon.path("(48).(49)").unwrap().string(), "one");
+		assert_eq!(acon.path("array.0").unwrap().string(), "zero");
+		assert_eq!(acon.path("array.(48)").unwrap().string(), "zero");
+		assert_eq!(acon.path("array.+"), None);
+		assert_eq!(acon.path(Path::new()), Some(&acon));
+	}
+
+	#[test]
+	fn plus_is_a_key_in_tables() {
+		let mut acon = "+ plus\n{ t\n\t+ nested\n}\n[ a\n]\n".parse::<Acon>().unwrap();
+		assert_eq!(acon.path("+").unwrap().string(), "plus");
+		assert_eq!(acon.path("t.+").unwrap().string(), "nested");
+		assert_eq!(acon.set_path("t.+", Acon::String("changed".to_string())).unwrap().unwrap().string(), "nested");
+		acon.set_path("new.+.x", Acon::String("y".to_string())).unwrap();
+		assert_eq!(acon.path("new.+.x").unwrap().string(), "y");
+		assert_eq!(acon.remove_path("+").unwrap().string(), "plus");
+		assert_eq!(acon.path("+"), None);
+		acon.set_path("a.+", Acon::String("appended".to_string())).unwrap();
+		assert_eq!(acon.path("a.0").unwrap().string(), "appended");
+		assert_eq!(acon.path("a.+"), None);
+		assert_eq!(::AconRef::parse("+ plus").unwrap().path("+").unwrap().as_str(), Some("plus"));
+	}
+}
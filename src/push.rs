@@ -0,0 +1,205 @@
+//! Parsing input that arrives in chunks
+//!
+//! The push parser is fed bytes as they arrive, in chunks that may end anywhere, even inside
+//! of a character. Complete lines go through the same state machine and builder as
+//! `from_str`, and the rest is kept until the next chunk completes it.
+
+use std::mem;
+use std::str;
+
+use event::State;
+use parse::Builder;
+use {Acon, Event, ParserOptions, ReadError, Span};
+
+/// Something the push parser found in the input
+#[derive(PartialEq, Clone, Debug)]
+pub enum Parsed {
+	/// An event, as the pull parser yields it
+	Event(Span, Event<'static>),
+	/// An entry, table or array at the root of the document is complete. Contains its key
+	/// and its value.
+	Value(String, Acon),
+}
+
+/// A parser that is pushed chunks of input
+///
+///  ```
+///  use acon::{Acon, Event, Parsed, Parser};
+///  let mut parser = Parser::new();
+///  assert_eq!(parser.feed(b"{ ser").unwrap(), []);
+///  let parsed = parser.feed(b"ver\n  host exam").unwrap();
+///  assert!(matches!(parsed[..], [Parsed::Event(_, Event::TableStart(ref name))] if name == "server"));
+///  let parsed = parser.feed(b"ple.com\n}\nport 80").unwrap();
+///  match parsed.last() {
+///      Some(&Parsed::Value(ref key, ref value)) => {
+///          assert_eq!(key, "server");
+///          assert_eq!(value.path("host").unwrap().string(), "example.com");
+///      }
+///      other => panic!("expected the server table, got {:?}", other),
+///  }
+///  let (parsed, value) = parser.finish().unwrap();
+///  assert_eq!(parsed.last(), Some(&Parsed::Value("port".to_string(), Acon::String("80".to_string()))));
+///  assert_eq!(value.path("server.host").unwrap().string(), "example.com");
+///  ```
+///
+/// After an error, every further call returns the same error.
+pub struct Parser {
+	state: State,
+	builder: Builder<'static, Acon>,
+	/// The start of a line whose end has not arrived yet
+	partial: Vec<u8>,
+	/// The key of the table or array that is open at the root
+	open: Option<String>,
+	error: Option<ReadError>,
+}
+
+impl Parser {
+	/// Create a parser with the default options
+	pub fn new() -> Parser {
+		Parser::with_options(ParserOptions::new())
+	}
+
+	/// Create a parser with the options, whose limits also apply to the buffered line
+	pub fn with_options(options: ParserOptions) -> Parser {
+		Parser { state: State::new(options), builder: Builder::new(options), partial: Vec::new(), open: None, error: None }
+	}
+
+	/// Parse the lines that the chunk completes, returning what was found in them
+	pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<Parsed>, ReadError> {
+		self.check()?;
+		let mut parsed = Vec::new();
+		let mut start = 0;
+		for (index, _) in chunk.iter().enumerate().filter(|&(_, &byte)| byte == b'\n') {
+			self.partial.extend_from_slice(&chunk[start..index + 1]);
+			start = index + 1;
+			let line = mem::take(&mut self.partial);
+			let result = self.line(&line, &mut parsed);
+			self.fail(result)?;
+		}
+		self.partial.extend_from_slice(&chunk[start..]);
+		let result = self.state.check_partial(&self.partial).map_err(ReadError::Parse);
+		self.fail(result)?;
+		Ok(parsed)
+	}
+
+	/// Parse the last line, which has no line terminator, and check that everything is
+	/// closed. Returns what was found in the last line, and the value of the whole input.
+	pub fn finish(mut self) -> Result<(Vec<Parsed>, Acon), ReadError> {
+		self.check()?;
+		let mut parsed = Vec::new();
+		let line = mem::take(&mut self.partial);
+		if !line.is_empty() {
+			self.line(&line, &mut parsed)?;
+		}
+		self.state.finish()?;
+		Ok((parsed, self.builder.finish()?))
+	}
+
+	fn line(&mut self, line: &[u8], parsed: &mut Vec<Parsed>) -> Result<(), ReadError> {
+		let line = match str::from_utf8(line) {
+			Ok(line) => line,
+			Err(error) => return Err(ReadError::InvalidUtf8(self.state.invalid_utf8(line, error))),
+		};
+		let (span, event) = match self.state.line(line)? {
+			Some((span, event)) => (span, event.into_owned()),
+			None => return Ok(()),
+		};
+		let at_root = self.state.depth() == 0;
+		let completed = match event {
+			Event::Entry(ref key, _) if at_root => Some(key.to_string()),
+			Event::TableStart(ref name) | Event::ArrayStart(ref name) if self.state.depth() == 1 => {
+				self.open = Some(name.to_string());
+				None
+			}
+			Event::End | Event::CloseAll if at_root => self.open.take(),
+			_ => None,
+		};
+		self.builder.event(span, event.clone())?;
+		parsed.push(Parsed::Event(span, event));
+		if let Some(key) = completed {
+			if let Some(value) = self.builder.root_entry(&key) {
+				parsed.push(Parsed::Value(key, value.clone()));
+			}
+		}
+		Ok(())
+	}
+
+	/// Return the error that stopped the parser, if any
+	fn check(&self) -> Result<(), ReadError> {
+		match self.error {
+			Some(ReadError::InvalidUtf8(span)) => Err(ReadError::InvalidUtf8(span)),
+			Some(ReadError::Parse(ref error)) => Err(ReadError::Parse(error.clone())),
+			_ => Ok(()),
+		}
+	}
+
+	/// Remember the error so that the parser stops
+	fn fail<T>(&mut self, result: Result<T, ReadError>) -> Result<T, ReadError> {
+		if let Err(ref error) = result {
+			self.error = match *error {
+				ReadError::InvalidUtf8(span) => Some(ReadError::InvalidUtf8(span)),
+				ReadError::Parse(ref error) => Some(ReadError::Parse(error.clone())),
+				ReadError::Io(_) => None,
+			};
+		}
+		result
+	}
+}
+
+impl Default for Parser {
+	fn default() -> Parser {
+		Parser::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use AconError;
+
+	/// Feed the input in chunks of every size and check that the result is always the same
+	#[test]
+	fn any_chunk_size() {
+		let input = "# comment\r\nk\u{e9}y v\u{e9}lue\n{ table\n\t[ list\n\t\tone\n\n\t]\n}\n[ top\n\t{ named\n$\nlast line";
+		let expected = input.parse::<Acon>().unwrap();
+		let events = ::PullParser::new(input).map(Result::unwrap).collect::<Vec<_>>();
+		for size in 1..input.len() + 1 {
+			let mut parser = Parser::new();
+			let mut parsed = Vec::new();
+			for chunk in input.as_bytes().chunks(size) {
+				parsed.extend(parser.feed(chunk).unwrap());
+			}
+			let (last, value) = parser.finish().unwrap();
+			parsed.extend(last);
+			assert_eq!(value, expected);
+			let found = parsed.iter().filter_map(|parsed| match *parsed {
+				Parsed::Event(span, ref event) => Some((span, event.clone())),
+				Parsed::Value(..) => None,
+			}).collect::<Vec<_>>();
+			assert_eq!(found, events);
+			let values = parsed.into_iter().filter_map(|parsed| match parsed {
+				Parsed::Value(key, value) => Some((key, value)),
+				Parsed::Event(..) => None,
+			}).collect::<Vec<_>>();
+			let keys = values.iter().map(|value| value.0.as_str()).collect::<Vec<_>>();
+			assert_eq!(keys, ["k\u{e9}y", "table", "top", "last"]);
+			assert_eq!(&values[2].1, expected.path("top").unwrap());
+		}
+	}
+
+	#[test]
+	fn errors_stop_the_parser() {
+		let mut parser = Parser::new();
+		assert!(parser.feed(b"a 1\n").is_ok());
+		assert!(matches!(parser.feed(b"a 2\nb 3\n"), Err(ReadError::Parse(AconError::OverwritingKey(span, None))) if span.line == 2));
+		assert!(matches!(parser.feed(b"c 4\n"), Err(ReadError::Parse(AconError::OverwritingKey(..)))));
+
+		let mut parser = Parser::new();
+		assert!(matches!(parser.feed(b"a \xff\n"), Err(ReadError::InvalidUtf8(span)) if span.start == 2));
+		assert!(matches!(parser.finish(), Err(ReadError::InvalidUtf8(_))));
+
+		let mut parser = Parser::with_options(ParserOptions::new().max_total_bytes(8));
+		assert!(parser.feed(b"a 1\nb").is_ok());
+		assert!(matches!(parser.feed(b"bbbbbb"), Err(ReadError::Parse(AconError::ExcessiveInput(span, 8))) if span.start == 8));
+	}
+}
@@ -0,0 +1,370 @@
+//! Queries selecting any number of values
+//!
+//! A query is written like a path, but a step may also be one of the following.
+//!
+//! * `*` selects every element of an array and every entry of a table.
+//! * `**` selects the value itself and everything nested inside of it.
+//! * `start:end` selects the array elements from `start` up to, but not including, `end`.
+//!   Either bound may be left out.
+//!
+//! Any step can be followed by predicates such as `[region=eu]`, which only keep the values
+//! where the path inside the brackets names a string equal to the value. Keys and values are
+//! unescaped, so a literal `*`, `:`, `[` or `=` is written with an escape code.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use {unescape, Acon, Path, Segment};
+
+/// Errors that come about when parsing a query
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum QueryError {
+	/// A step has a `[` without a matching `]`
+	UnclosedBracket(String),
+	/// A step has text that is neither part of its selector nor a predicate
+	UnexpectedText(String),
+	/// A predicate has no `=` or contains a `[`
+	InvalidPredicate(String),
+	/// A slice has bounds that are not numbers
+	InvalidSlice(String),
+}
+
+impl fmt::Display for QueryError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			QueryError::UnclosedBracket(ref step) => write!(f, "{}: unclosed bracket", step),
+			QueryError::UnexpectedText(ref text) => write!(f, "{}: unexpected text", text),
+			QueryError::InvalidPredicate(ref predicate) => {
+				write!(f, "{}: a predicate is written as [key=value]", predicate)
+			}
+			QueryError::InvalidSlice(ref slice) => write!(f, "{}: a slice is written as start:end", slice),
+		}
+	}
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+enum Selector {
+	Child(Segment),
+	Wildcard,
+	Recursive,
+	Slice(usize, Option<usize>),
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct Predicate {
+	path: Path,
+	value: String,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct Step {
+	selector: Selector,
+	predicates: Vec<Predicate>,
+}
+
+/// A parsed query
+///
+///  ```
+///  use acon::{Acon, Query};
+///  let input = r#"
+///  [ servers
+///    {
+///      region eu
+///      host web.example.com
+///    }
+///    {
+///      region us
+///      host db.example.com
+///    }
+///  ]
+///  "#;
+///  let result = input.parse::<Acon>().unwrap();
+///  let query = "servers.*[region=eu].host".parse::<Query>().unwrap();
+///  let matches = query.matches(&result);
+///  assert_eq!(matches.len(), 1);
+///  assert_eq!(matches[0].0.to_string(), "servers.0.host");
+///  assert_eq!(matches[0].1.string(), "web.example.com");
+///  ```
+///
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Query {
+	steps: Vec<Step>,
+}
+
+impl Query {
+	/// Find every value selected by the query, along with its path, in document order
+	pub fn matches<'a>(&self, acon: &'a Acon) -> Vec<(Path, &'a Acon)> {
+		let mut current = vec![(Path::new(), acon)];
+		for step in &self.steps {
+			let mut next = Vec::new();
+			for (path, value) in current {
+				step.select(path, value, &mut next);
+			}
+			// Recursive steps can reach the same value from several places
+			let mut seen = BTreeSet::new();
+			next.retain(|match_| seen.insert(match_.0.clone()));
+			current = next;
+		}
+		current
+	}
+}
+
+impl Step {
+	fn select<'a>(&self, path: Path, value: &'a Acon, out: &mut Vec<(Path, &'a Acon)>) {
+		let start = out.len();
+		match self.selector {
+			Selector::Child(ref segment) => {
+				if let Some(child) = value.get_segment(segment) {
+					out.push((path.join(concrete(value, segment)), child));
+				}
+			}
+			Selector::Wildcard => children(&path, value, out),
+			Selector::Recursive => descendants(path, value, out),
+			Selector::Slice(from, to) => {
+				if let Acon::Array(ref array) = *value {
+					let to = to.unwrap_or(array.len());
+					for (index, element) in array.iter().enumerate().take(to).skip(from) {
+						out.push((path.join(index), element));
+					}
+				}
+			}
+		}
+		let mut index = start;
+		while index < out.len() {
+			if self.predicates.iter().all(|predicate| predicate.holds(out[index].1)) {
+				index += 1;
+			} else {
+				out.remove(index);
+			}
+		}
+	}
+}
+
+impl Predicate {
+	fn holds(&self, value: &Acon) -> bool {
+		value.path(&self.path).and_then(Acon::as_str) == Some(self.value.as_str())
+	}
+}
+
+/// The segment that names the child in this value, so that an index or `+` in a table becomes
+/// a key
+fn concrete(value: &Acon, segment: &Segment) -> Segment {
+	match (value.is_table(), segment) {
+		(true, &Segment::Index(_)) | (true, &Segment::Append) => Segment::Key(segment.key().into_owned()),
+		_ => segment.clone(),
+	}
+}
+
+fn children<'a>(path: &Path, value: &'a Acon, out: &mut Vec<(Path, &'a Acon)>) {
+	match *value {
+		Acon::Array(ref array) => {
+			for (index, element) in array.iter().enumerate() {
+				out.push((path.join(index), element));
+			}
+		}
+		Acon::String(_) => {}
+		Acon::Table(ref table) => {
+			for (key, entry) in table {
+				out.push((path.join(key.as_str()), entry));
+			}
+		}
+	}
+}
+
+fn descendants<'a>(path: Path, value: &'a Acon, out: &mut Vec<(Path, &'a Acon)>) {
+	let mut nested = Vec::new();
+	children(&path, value, &mut nested);
+	out.push((path, value));
+	for (path, child) in nested {
+		descendants(path, child, out);
+	}
+}
+
+impl FromStr for Query {
+	type Err = QueryError;
+
+	fn from_str(string: &str) -> Result<Query, QueryError> {
+		let mut steps = Vec::new();
+		let mut depth = 0;
+		let mut begin = 0;
+		for (index, character) in string.char_indices() {
+			match character {
+				'[' => depth += 1,
+				']' if depth > 0 => depth -= 1,
+				'.' if depth == 0 => {
+					steps.push(parse_step(&string[begin..index])?);
+					begin = index + 1;
+				}
+				_ => {}
+			}
+		}
+		steps.push(parse_step(&string[begin..])?);
+		Ok(Query { steps })
+	}
+}
+
+fn parse_step(step: &str) -> Result<Step, QueryError> {
+	let (selector, mut rest) = step.split_at(step.find('[').unwrap_or(step.len()));
+	if selector.contains(']') {
+		return Err(QueryError::UnexpectedText(step.to_string()));
+	}
+	let mut predicates = Vec::new();
+	while !rest.is_empty() {
+		if !rest.starts_with('[') {
+			return Err(QueryError::UnexpectedText(rest.to_string()));
+		}
+		let end = rest.find(']').ok_or_else(|| QueryError::UnclosedBracket(step.to_string()))?;
+		predicates.push(parse_predicate(&rest[1..end])?);
+		rest = &rest[end + 1..];
+	}
+	Ok(Step { selector: parse_selector(selector)?, predicates })
+}
+
+fn parse_selector(selector: &str) -> Result<Selector, QueryError> {
+	Ok(match selector {
+		"*" => Selector::Wildcard,
+		"**" => Selector::Recursive,
+		_ => match selector.split_once(':') {
+			Some((from, to)) => {
+				let invalid = || QueryError::InvalidSlice(selector.to_string());
+				let from = if from.is_empty() { 0 } else { from.parse().map_err(|_| invalid())? };
+				let to = if to.is_empty() { None } else { Some(to.parse().map_err(|_| invalid())?) };
+				Selector::Slice(from, to)
+			}
+			None => Selector::Child(Segment::parse(selector)),
+		},
+	})
+}
+
+fn parse_predicate(predicate: &str) -> Result<Predicate, QueryError> {
+	match predicate.split_once('=') {
+		Some((path, value)) if !predicate.contains('[') => {
+			Ok(Predicate { path: Path::from(path), value: unescape(value) })
+		}
+		_ => Err(QueryError::InvalidPredicate(predicate.to_string())),
+	}
+}
+
+impl Acon {
+	/// Find every value selected by a query, along with its path
+	///
+	///  ```
+	///  use acon::Acon;
+	///  let result = "{ a\n  onclick open\n  { b\n    onclick close\n  }\n}".parse::<Acon>().unwrap();
+	///  let found = result.query("**.onclick").unwrap();
+	///  let paths = found.iter().map(|&(ref path, _)| path.to_string()).collect::<Vec<_>>();
+	///  assert_eq!(paths, ["a.onclick", "a.b.onclick"]);
+	///  ```
+	///
+	pub fn query(&self, query: &str) -> Result<Vec<(Path, &Acon)>, QueryError> {
+		Ok(query.parse::<Query>()?.matches(self))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const MENU: &str = "
+	{ menu
+		{ popup
+			[ menuitem
+				{
+					value New
+					onclick CreateNewDoc()
+				}
+				{
+					value Open
+					onclick OpenDoc()
+				}
+				{
+					value Close
+				}
+			]
+		}
+		onclick Menu()
+	}
+	";
+
+	fn paths(acon: &Acon, query: &str) -> Vec<String> {
+		acon.query(query).unwrap().iter().map(|match_| match_.0.to_string()).collect()
+	}
+
+	#[test]
+	fn wildcards() {
+		let acon = MENU.parse::<Acon>().unwrap();
+		let values = acon.query("menu.popup.menuitem.*.value").unwrap();
+		let values = values.iter().map(|&(_, value)| value.string().as_str()).collect::<Vec<_>>();
+		assert_eq!(values, ["New", "Open", "Close"]);
+		// Tables keep the order of the document with preserve_order
+		let mut entries = ["menu.popup.menuitem.1.onclick", "menu.popup.menuitem.1.value"];
+		let mut children = ["menu.onclick", "menu.popup"];
+		if cfg!(feature = "preserve_order") {
+			entries.reverse();
+			children.reverse();
+		}
+		assert_eq!(paths(&acon, "menu.popup.menuitem.1.*"), entries);
+		assert_eq!(paths(&acon, "menu.*"), children);
+		assert_eq!(paths(&acon, "menu.onclick.*"), Vec::<String>::new());
+	}
+
+	#[test]
+	fn recursive_descent() {
+		let acon = MENU.parse::<Acon>().unwrap();
+		assert_eq!(paths(&acon, "**.onclick"), [
+			"menu.onclick", "menu.popup.menuitem.0.onclick", "menu.popup.menuitem.1.onclick",
+		]);
+		assert_eq!(paths(&acon, "**.**.onclick").len(), 3);
+		assert_eq!(paths(&acon, "**").len(), 13);
+	}
+
+	#[test]
+	fn slices() {
+		let acon = "[ array\n  a\n  b\n  c\n]\n{ table\n}".parse::<Acon>().unwrap();
+		assert_eq!(paths(&acon, "array.1:"), ["array.1", "array.2"]);
+		assert_eq!(paths(&acon, "array.:2"), ["array.0", "array.1"]);
+		assert_eq!(paths(&acon, "array.1:9"), ["array.1", "array.2"]);
+		assert_eq!(paths(&acon, "array.2:1"), Vec::<String>::new());
+		assert_eq!(paths(&acon, "table.:"), Vec::<String>::new());
+	}
+
+	#[test]
+	fn predicates() {
+		let acon = "
+		{ servers
+			{ a
+				region eu
+				host a.example.com
+			}
+			{ b
+				region us
+				host b.example.com
+			}
+			{ c
+				region eu
+				host c.example.com
+				{ tags
+					tier front end
+				}
+			}
+		}
+		".parse::<Acon>().unwrap();
+		assert_eq!(paths(&acon, "servers.*[region=eu].host"), ["servers.a.host", "servers.c.host"]);
+		assert_eq!(paths(&acon, "servers.*[region=eu][tags.tier=front(32)end]"), ["servers.c"]);
+		assert_eq!(paths(&acon, "servers.*[region=asia]"), Vec::<String>::new());
+		assert_eq!(paths(&acon, "servers.*[tags=eu]"), Vec::<String>::new());
+	}
+
+	#[test]
+	fn invalid_queries() {
+		assert_eq!("a.b[c=d".parse::<Query>(), Err(QueryError::UnclosedBracket("b[c=d".to_string())));
+		assert_eq!("a.b[c=d]e".parse::<Query>(), Err(QueryError::UnexpectedText("e".to_string())));
+		assert_eq!("a.b]".parse::<Query>(), Err(QueryError::UnexpectedText("b]".to_string())));
+		assert_eq!("a[c]".parse::<Query>(), Err(QueryError::InvalidPredicate("c".to_string())));
+		assert_eq!("a.1:x".parse::<Query>(), Err(QueryError::InvalidSlice("1:x".to_string())));
+		assert!("a.b[c.d=e.f].g".parse::<Query>().is_ok());
+	}
+}
@@ -0,0 +1,295 @@
+//! Reading a stream of records
+//!
+//! Programs can append to an ACON file record by record. The iterator reads one line at a
+//! time and yields each record as soon as its last line is read. With `follow`, it keeps
+//! waiting for the rest of a file that is still being written instead of ending with it. A
+//! record that can not be parsed yields an error, after which the rest of that record is
+//! skipped and the next record is read as usual.
+
+use std::io::BufRead;
+use std::mem;
+use std::str;
+
+use event::State;
+use parse::Builder;
+use {Acon, Event, ParserOptions, ReadError, Span};
+
+/// Where one record ends and the next begins
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(crate) enum Boundary {
+	/// Every entry of the root table is a record
+	Entry,
+	/// A `$` ends a record of all entries since the previous `$`
+	Dollar,
+}
+
+/// An iterator over the records of a stream, where each record is a table
+///
+///  ```
+///  use acon::{Acon, Records};
+///  let input = "event start\n{ request\n  path /index.html\n  status 200\n}\n";
+///  let records = Records::new(input.as_bytes()).collect::<Result<Vec<_>, _>>().unwrap();
+///  assert_eq!(records.len(), 2);
+///  assert_eq!(records[0].path("event").unwrap().string(), "start");
+///  assert_eq!(records[1].path("request.status").unwrap().string(), "200");
+///
+///  let input = "time 12:00\nlevel info\n$\ntime 12:01\nlevel warning\n$\n";
+///  let records = Records::dollar_terminated(input.as_bytes()).collect::<Result<Vec<_>, _>>().unwrap();
+///  assert_eq!(records.len(), 2);
+///  assert_eq!(records[1].path("level").unwrap().string(), "warning");
+///  ```
+///
+/// Spans in errors count lines and bytes from the start of the stream.
+pub struct Records<R: BufRead> {
+	reader: R,
+	boundary: Boundary,
+	options: ParserOptions,
+	state: State,
+	builder: Builder<'static, Acon>,
+	/// Whether the current record failed and is skipped up to its end
+	skipping: bool,
+	/// The line being read, which is only kept across calls when following the stream
+	buffer: Vec<u8>,
+	follow: bool,
+	done: bool,
+}
+
+impl<R: BufRead> Records<R> {
+	/// Read a record for every entry, table and array at the root of the stream
+	pub fn new(reader: R) -> Records<R> {
+		ParserOptions::new().records(reader)
+	}
+
+	/// Read a record for every `$` line, made of all entries since the previous `$` line.
+	/// Entries after the last `$` form a record at the end of the stream.
+	pub fn dollar_terminated(reader: R) -> Records<R> {
+		ParserOptions::new().dollar_terminated_records(reader)
+	}
+
+	pub(crate) fn with_boundary(reader: R, boundary: Boundary, options: ParserOptions) -> Records<R> {
+		Records {
+			reader,
+			boundary,
+			options,
+			state: State::new(options),
+			builder: Builder::new(options),
+			skipping: false,
+			buffer: Vec::new(),
+			follow: false,
+			done: false,
+		}
+	}
+
+	/// Follow a stream that is still being written, such as a file that another program
+	/// appends records to. At the end of the stream, `next` returns `None` without ending
+	/// the record that is being read, and can be called again once more has been written. A
+	/// line is only parsed once its line terminator has been written.
+	///
+	///  ```
+	///  use acon::Records;
+	///  use std::io::Cursor;
+	///  let mut records = Records::new(Cursor::new(b"a 1\n{ b\n".to_vec())).follow();
+	///  assert_eq!(records.next().unwrap().unwrap().to_string(), "a 1\n");
+	///  assert!(records.next().is_none());
+	///  ```
+	///
+	pub fn follow(mut self) -> Records<R> {
+		self.follow = true;
+		self
+	}
+
+	/// Take the current record and start the next one. Failed and empty records are skipped.
+	fn end_record(&mut self) -> Option<Acon> {
+		let builder = mem::replace(&mut self.builder, Builder::new(self.options));
+		if mem::replace(&mut self.skipping, false) {
+			return None;
+		}
+		match builder.finish() {
+			Ok(Acon::Table(ref table)) if table.is_empty() => None,
+			Ok(record) => Some(record),
+			Err(_) => None,
+		}
+	}
+
+	/// Parse the line in the buffer. A line that is not valid UTF-8 is still given to the
+	/// state, so that its delimiters are kept track of while the record is skipped.
+	fn line(&mut self) -> Result<Option<(Span, Event<'static>)>, ReadError> {
+		match str::from_utf8(&self.buffer) {
+			Ok(line) => Ok(self.state.line(line)?.map(|(span, event)| (span, event.into_owned()))),
+			Err(error) => {
+				let span = self.state.invalid_utf8(&self.buffer, error);
+				let _ = self.state.line(&String::from_utf8_lossy(&self.buffer));
+				Err(ReadError::InvalidUtf8(span))
+			}
+		}
+	}
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+	type Item = Result<Acon, ReadError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		while !self.done {
+			if !self.follow || self.buffer.ends_with(b"\n") {
+				self.buffer.clear();
+			}
+			match self.reader.read_until(b'\n', &mut self.buffer) {
+				// The rest of the stream is not written yet
+				Ok(_) if self.follow && !self.buffer.ends_with(b"\n") => return None,
+				Ok(0) => {
+					self.done = true;
+					return match self.state.finish() {
+						Err(_) if self.skipping => None,
+						Err(error) => Some(Err(ReadError::Parse(error))),
+						Ok(()) => self.end_record().map(Ok),
+					};
+				}
+				Ok(_) => {}
+				Err(error) => {
+					self.done = true;
+					return Some(Err(ReadError::Io(error)));
+				}
+			}
+			let (result, close_all) = match self.line() {
+				Ok(Some((span, event))) => {
+					let close_all = event == Event::CloseAll;
+					if self.skipping {
+						(Ok(()), close_all)
+					} else {
+						(self.builder.event(span, event).map_err(ReadError::Parse), close_all)
+					}
+				}
+				Ok(None) => continue,
+				Err(error) => (Err(error), false),
+			};
+			let at_boundary = match self.boundary {
+				Boundary::Entry => self.state.depth() == 0,
+				Boundary::Dollar => close_all,
+			};
+			// Only the first error of a record is reported
+			let error = match result {
+				Err(error) if !self.skipping => Some(error),
+				_ => None,
+			};
+			self.skipping |= error.is_some();
+			let record = if at_boundary { self.end_record() } else { None };
+			if let Some(error) = error {
+				return Some(Err(error));
+			}
+			if let Some(record) = record {
+				return Some(Ok(record));
+			}
+		}
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cell::RefCell;
+	use std::io::{self, BufReader, Cursor, Read};
+	use std::rc::Rc;
+	use {AconError, DuplicateKeys};
+
+	fn records(records: Records<&[u8]>) -> Vec<Result<String, usize>> {
+		records.map(|record| match record {
+			Ok(record) => Ok(record.to_string()),
+			Err(ReadError::Parse(error)) => Err(error.span().line),
+			Err(ReadError::InvalidUtf8(span)) => Err(span.line),
+			Err(ReadError::Io(error)) => panic!("unexpected I/O error {}", error),
+		}).collect()
+	}
+
+	#[test]
+	fn record_per_entry() {
+		let input = "# comment\na 1\n{ b\n\tc 2\n}\n\n[ d\n\te\n]\n{ f\n\t[ g\n$\na 3\n";
+		assert_eq!(records(Records::new(input.as_bytes())), [
+			Ok("a 1\n".to_string()),
+			Ok("{ b\n\tc 2\n}\n".to_string()),
+			Ok("[ d\n\te\n]\n".to_string()),
+			Ok("{ f\n\t[ g\n\t]\n}\n".to_string()),
+			Ok("a 3\n".to_string()),
+		]);
+	}
+
+	#[test]
+	fn record_per_dollar() {
+		let input = "a 1\nb 2\n$\n$\n{ c\n\td 3\n$\na 4\n";
+		assert_eq!(records(Records::dollar_terminated(input.as_bytes())), [
+			Ok("a 1\nb 2\n".to_string()),
+			Ok("{ c\n\td 3\n}\n".to_string()),
+			Ok("a 4\n".to_string()),
+		]);
+		assert_eq!(records(Records::dollar_terminated("a 1\n{ b\n".as_bytes())), [Err(2)]);
+	}
+
+	#[test]
+	fn errors_are_localized() {
+		let input = b"a 1\n{ b\n\tc 2\n\tc 3\n\t[ x\n\t}\n}\n}\n{ d\n\t[ \xff\n\t]\n}\n{ e\n\tf 4\n}\n{ g\n".to_vec();
+		assert_eq!(records(Records::new(&input[..])), [
+			Ok("a 1\n".to_string()),
+			Err(4),
+			Err(8),
+			Err(10),
+			Ok("{ e\n\tf 4\n}\n".to_string()),
+			Err(16),
+		]);
+		let input = "a 1\na 2\n$\nb 3\n$\n";
+		assert_eq!(records(Records::dollar_terminated(input.as_bytes())), [Err(2), Ok("b 3\n".to_string())]);
+		assert!(matches!(Records::new("}\n".as_bytes()).next(),
+		                 Some(Err(ReadError::Parse(AconError::ExcessiveClosingDelimiter(_))))));
+	}
+
+	#[test]
+	fn yields_records_before_the_stream_ends() {
+		struct Pending;
+		impl Read for Pending {
+			fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+				panic!("the next record is not written yet");
+			}
+		}
+		let stream = BufReader::new(Cursor::new("a 1\n{ b\n\tc 2\n}\n").chain(Pending));
+		let mut records = Records::new(stream);
+		assert_eq!(records.next().unwrap().unwrap().to_string(), "a 1\n");
+		assert_eq!(records.next().unwrap().unwrap().to_string(), "{ b\n\tc 2\n}\n");
+	}
+
+	#[test]
+	fn follows_a_growing_stream() {
+		/// A file that is read while it is still being written
+		struct Growing(Rc<RefCell<Vec<u8>>>, usize);
+		impl Read for Growing {
+			fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+				let read = (&self.0.borrow()[self.1..]).read(buffer)?;
+				self.1 += read;
+				Ok(read)
+			}
+		}
+		let written = Rc::new(RefCell::new(b"a 1\n{ b\n\tc".to_vec()));
+		let mut records = Records::new(BufReader::new(Growing(written.clone(), 0))).follow();
+		assert_eq!(records.next().unwrap().unwrap().to_string(), "a 1\n");
+		assert!(records.next().is_none());
+		written.borrow_mut().extend_from_slice(b" 2\n}");
+		assert!(records.next().is_none());
+		written.borrow_mut().extend_from_slice(b"\nd 3\n");
+		assert_eq!(records.next().unwrap().unwrap().to_string(), "{ b\n\tc 2\n}\n");
+		assert_eq!(records.next().unwrap().unwrap().to_string(), "d 3\n");
+		assert!(records.next().is_none());
+	}
+
+	#[test]
+	fn applies_options() {
+		let options = ParserOptions::new().max_depth(1).duplicate_keys(DuplicateKeys::LastWins);
+		let input = "a 1\n{ b\n\t{ c\n\t}\n}\nd 2\nd 3\n$\n";
+		let mut records = options.records(input.as_bytes());
+		assert_eq!(records.next().unwrap().unwrap().to_string(), "a 1\n");
+		assert!(matches!(records.next(), Some(Err(ReadError::Parse(AconError::ExcessiveDepth(..))))));
+		assert_eq!(records.next().unwrap().unwrap().to_string(), "d 2\n");
+		let records = options.dollar_terminated_records(input.as_bytes()).collect::<Vec<_>>();
+		assert!(matches!(records[0], Err(ReadError::Parse(AconError::ExcessiveDepth(..)))));
+		let records = options.max_depth(2).dollar_terminated_records(input.as_bytes());
+		assert_eq!(records.map(|record| record.unwrap().to_string()).collect::<Vec<_>>(),
+		           ["a 1\n{ b\n\t{ c\n\t}\n}\nd 3\n"]);
+	}
+}
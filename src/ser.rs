@@ -0,0 +1,377 @@
+//! Serialize Rust data structures into ACON
+//!
+//! Structs and maps become tables, sequences and tuples become arrays, and primitives
+//! become strings. `None` and `()` become empty strings. An enum variant with data becomes
+//! a table with a single entry named after the variant.
+
+use serde::ser::{self, Serialize};
+
+use {check_writable, escape, Acon, Array, Path, PathError, SerdeError, Table};
+
+/// Serialize an instance of `T` into ACON text
+///
+/// The root of an ACON document is a table, so `T` must serialize into a table. A string can
+/// not be written under an empty key, such as an empty key of a map.
+///
+///  ```
+///  #[macro_use]
+///  extern crate serde_derive;
+///  extern crate acon;
+///
+///  #[derive(Serialize)]
+///  struct Config {
+///      name: String,
+///      hosts: Vec<String>,
+///  }
+///
+///  let config = Config { name: "web".to_string(), hosts: vec!["a".to_string()] };
+///  let text = acon::to_string(&config).unwrap();
+///  if cfg!(feature = "preserve_order") {
+///      assert_eq!(text, "name web\n[ hosts\n\ta\n]\n");
+///  } else {
+///      assert_eq!(text, "[ hosts\n\ta\n]\nname web\n");
+///  }
+///  ```
+///
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, SerdeError> {
+	match to_acon(value)? {
+		acon @ Acon::Table(_) => match check_writable(&acon, &mut Path::new()) {
+			Err(PathError::Unwritable(path)) => Err(SerdeError::Message {
+				path,
+				message: "a string can not have an empty key".to_string(),
+			}),
+			_ => Ok(acon.to_string()),
+		},
+		_ => Err(SerdeError::Message {
+			path: String::new(),
+			message: "the root of an ACON document must be a table".to_string(),
+		}),
+	}
+}
+
+/// Serialize an instance of `T` into an ACON value
+pub fn to_acon<T: Serialize + ?Sized>(value: &T) -> Result<Acon, SerdeError> {
+	value.serialize(Serializer)
+}
+
+/// A serializer producing ACON values
+pub struct Serializer;
+
+fn string<T: ToString>(value: T) -> Result<Acon, SerdeError> {
+	Ok(Acon::String(value.to_string()))
+}
+
+fn variant(name: &str, value: Acon) -> Acon {
+	let mut table = Table::new();
+	table.insert(name.to_string(), value);
+	Acon::Table(table)
+}
+
+impl ser::Serializer for Serializer {
+	type Ok = Acon;
+	type Error = SerdeError;
+	type SerializeSeq = SerializeArray;
+	type SerializeTuple = SerializeArray;
+	type SerializeTupleStruct = SerializeArray;
+	type SerializeTupleVariant = SerializeArray;
+	type SerializeMap = SerializeTable;
+	type SerializeStruct = SerializeTable;
+	type SerializeStructVariant = SerializeTable;
+
+	fn serialize_bool(self, value: bool) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_i8(self, value: i8) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_i16(self, value: i16) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_i32(self, value: i32) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_i64(self, value: i64) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_i128(self, value: i128) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_u8(self, value: u8) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_u16(self, value: u16) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_u32(self, value: u32) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_u64(self, value: u64) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_u128(self, value: u128) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_f32(self, value: f32) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_f64(self, value: f64) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_char(self, value: char) -> Result<Acon, SerdeError> { string(value) }
+	fn serialize_str(self, value: &str) -> Result<Acon, SerdeError> { string(value) }
+
+	fn serialize_bytes(self, value: &[u8]) -> Result<Acon, SerdeError> {
+		Ok(Acon::Array(value.iter().map(|byte| Acon::String(byte.to_string())).collect()))
+	}
+
+	fn serialize_none(self) -> Result<Acon, SerdeError> {
+		string("")
+	}
+
+	fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Acon, SerdeError> {
+		value.serialize(self)
+	}
+
+	fn serialize_unit(self) -> Result<Acon, SerdeError> {
+		string("")
+	}
+
+	fn serialize_unit_struct(self, _name: &'static str) -> Result<Acon, SerdeError> {
+		string("")
+	}
+
+	fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str)
+		-> Result<Acon, SerdeError> {
+		string(variant)
+	}
+
+	fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T)
+		-> Result<Acon, SerdeError> {
+		value.serialize(self)
+	}
+
+	fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32,
+	                                                    name: &'static str, value: &T)
+		-> Result<Acon, SerdeError> {
+		Ok(variant(name, value.serialize(self)?))
+	}
+
+	fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, SerdeError> {
+		Ok(SerializeArray { array: Array::with_capacity(len.unwrap_or(0)), variant: None })
+	}
+
+	fn serialize_tuple(self, len: usize) -> Result<SerializeArray, SerdeError> {
+		self.serialize_seq(Some(len))
+	}
+
+	fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeArray, SerdeError> {
+		self.serialize_seq(Some(len))
+	}
+
+	fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize)
+		-> Result<SerializeArray, SerdeError> {
+		Ok(SerializeArray { array: Array::with_capacity(len), variant: Some(variant) })
+	}
+
+	fn serialize_map(self, _len: Option<usize>) -> Result<SerializeTable, SerdeError> {
+		Ok(SerializeTable { table: Table::new(), key: None, variant: None })
+	}
+
+	fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeTable, SerdeError> {
+		self.serialize_map(Some(len))
+	}
+
+	fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str, _len: usize)
+		-> Result<SerializeTable, SerdeError> {
+		Ok(SerializeTable { table: Table::new(), key: None, variant: Some(variant) })
+	}
+}
+
+#[doc(hidden)]
+pub struct SerializeArray {
+	array: Array,
+	variant: Option<&'static str>,
+}
+
+impl SerializeArray {
+	fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+		let index = self.array.len().to_string();
+		self.array.push(value.serialize(Serializer).map_err(|error| error.within(&index))?);
+		Ok(())
+	}
+
+	fn finish(self) -> Result<Acon, SerdeError> {
+		let array = Acon::Array(self.array);
+		Ok(match self.variant {
+			Some(name) => variant(name, array),
+			None => array,
+		})
+	}
+}
+
+impl ser::SerializeSeq for SerializeArray {
+	type Ok = Acon;
+	type Error = SerdeError;
+
+	fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+		self.push(value)
+	}
+
+	fn end(self) -> Result<Acon, SerdeError> {
+		self.finish()
+	}
+}
+
+impl ser::SerializeTuple for SerializeArray {
+	type Ok = Acon;
+	type Error = SerdeError;
+
+	fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+		self.push(value)
+	}
+
+	fn end(self) -> Result<Acon, SerdeError> {
+		self.finish()
+	}
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+	type Ok = Acon;
+	type Error = SerdeError;
+
+	fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+		self.push(value)
+	}
+
+	fn end(self) -> Result<Acon, SerdeError> {
+		self.finish()
+	}
+}
+
+impl ser::SerializeTupleVariant for SerializeArray {
+	type Ok = Acon;
+	type Error = SerdeError;
+
+	fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+		self.push(value)
+	}
+
+	fn end(self) -> Result<Acon, SerdeError> {
+		self.finish()
+	}
+}
+
+#[doc(hidden)]
+pub struct SerializeTable {
+	table: Table,
+	key: Option<String>,
+	variant: Option<&'static str>,
+}
+
+impl SerializeTable {
+	fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), SerdeError> {
+		let value = value.serialize(Serializer).map_err(|error| error.within(&escape(&key)))?;
+		self.table.insert(key, value);
+		Ok(())
+	}
+
+	fn finish(self) -> Result<Acon, SerdeError> {
+		let table = Acon::Table(self.table);
+		Ok(match self.variant {
+			Some(name) => variant(name, table),
+			None => table,
+		})
+	}
+}
+
+impl ser::SerializeMap for SerializeTable {
+	type Ok = Acon;
+	type Error = SerdeError;
+
+	fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+		match key.serialize(Serializer)? {
+			Acon::String(key) => {
+				self.key = Some(key);
+				Ok(())
+			}
+			_ => Err(SerdeError::Message { path: String::new(), message: "keys must be strings".to_string() }),
+		}
+	}
+
+	fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+		let key = self.key.take().expect("serialize_value is called after serialize_key");
+		self.insert(key, value)
+	}
+
+	fn end(self) -> Result<Acon, SerdeError> {
+		self.finish()
+	}
+}
+
+impl ser::SerializeStruct for SerializeTable {
+	type Ok = Acon;
+	type Error = SerdeError;
+
+	fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+		self.insert(key.to_string(), value)
+	}
+
+	fn end(self) -> Result<Acon, SerdeError> {
+		self.finish()
+	}
+}
+
+impl ser::SerializeStructVariant for SerializeTable {
+	type Ok = Acon;
+	type Error = SerdeError;
+
+	fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+		self.insert(key.to_string(), value)
+	}
+
+	fn end(self) -> Result<Acon, SerdeError> {
+		self.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use from_str;
+	use std::collections::BTreeMap;
+
+	#[derive(Serialize, Deserialize, Debug, PartialEq)]
+	enum Shape {
+		Point,
+		Circle(f32),
+		Line(i32, i32),
+		Rectangle { width: u32, height: u32 },
+	}
+
+	#[derive(Serialize, Deserialize, Debug, PartialEq)]
+	struct Drawing {
+		title: String,
+		note: Option<String>,
+		shapes: Vec<Shape>,
+		layers: BTreeMap<u8, Vec<bool>>,
+		origin: (i64, i64),
+	}
+
+	#[test]
+	fn round_trip() {
+		let mut layers = BTreeMap::new();
+		layers.insert(1, vec![true, false]);
+		let drawing = Drawing {
+			title: "  spaced title\nwith a line ".to_string(),
+			note: None,
+			shapes: vec![Shape::Point, Shape::Circle(1.5), Shape::Line(-1, 2),
+			             Shape::Rectangle { width: 3, height: 4 }],
+			layers,
+			origin: (0, -7),
+		};
+		let string = to_string(&drawing).unwrap();
+		assert_eq!(from_str::<Drawing>(&string).unwrap(), drawing);
+	}
+
+	#[test]
+	fn root_must_be_table() {
+		assert!(to_string(&vec![1, 2]).is_err());
+		assert_eq!(to_acon(&vec![1, 2]).unwrap(), Acon::Array(vec![Acon::String("1".to_string()),
+		                                                          Acon::String("2".to_string())]));
+	}
+
+	#[test]
+	fn map_keys_must_be_strings() {
+		let mut map = BTreeMap::new();
+		map.insert(vec![1], 1);
+		let mut outer = BTreeMap::new();
+		outer.insert("outer", map);
+		assert_eq!(to_string(&outer).unwrap_err().to_string(), "outer: keys must be strings");
+	}
+
+	#[test]
+	fn strings_need_keys() {
+		let mut map = BTreeMap::new();
+		map.insert("", "value");
+		let mut outer = BTreeMap::new();
+		outer.insert("outer", map);
+		assert_eq!(to_string(&outer).unwrap_err().to_string(), "outer.: a string can not have an empty key");
+		let mut map = BTreeMap::new();
+		map.insert("", vec!["value"]);
+		assert_eq!(to_string(&map).unwrap(), "[\n\tvalue\n]\n");
+	}
+}
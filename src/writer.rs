@@ -0,0 +1,217 @@
+//! Writing ACON incrementally
+//!
+//! The writer produces the same text as `Display` for `Acon`, one line at a time, so a
+//! document never needs to be held in memory. If the writer is dropped before every table and
+//! array is closed, as happens when the program panics, it writes `$` so that everything that
+//! was written can still be parsed.
+
+use std::io::Write;
+
+use {escape, escape_value, Kind, WriteError};
+
+/// A writer of ACON text
+///
+///  ```
+///  use acon::{Acon, AconWriter};
+///  let mut output = Vec::new();
+///  {
+///      let mut writer = AconWriter::new(&mut output);
+///      writer.entry("name", "my server").unwrap();
+///      writer.begin_array("ports").unwrap();
+///      writer.item("80").unwrap();
+///      writer.item("443").unwrap();
+///      writer.end().unwrap();
+///      writer.begin_table("tls").unwrap();
+///      writer.entry("certificate path", "/etc/cert.pem").unwrap();
+///  }
+///  let output = String::from_utf8(output).unwrap();
+///  assert_eq!(output, "name my server\n[ ports\n\t80\n\t443\n]\n{ tls\n\tcertificate(32)path /etc/cert.pem\n$\n");
+///  let result = output.parse::<Acon>().unwrap();
+///  assert_eq!(result.path("tls.certificate(32)path").unwrap().string(), "/etc/cert.pem");
+///  ```
+///
+pub struct AconWriter<W: Write> {
+	writer: Option<W>,
+	open: Vec<Kind>,
+}
+
+impl<W: Write> AconWriter<W> {
+	/// Create a writer at the root of a document
+	pub fn new(writer: W) -> AconWriter<W> {
+		AconWriter { writer: Some(writer), open: Vec::new() }
+	}
+
+	/// Open a table. In an array, a table with a name becomes a table with a single entry.
+	pub fn begin_table(&mut self, name: &str) -> Result<(), WriteError> {
+		self.opening("{", name)?;
+		self.open.push(Kind::Table);
+		Ok(())
+	}
+
+	/// Open an array. In an array, an array with a name becomes a table with a single entry.
+	pub fn begin_array(&mut self, name: &str) -> Result<(), WriteError> {
+		self.opening("[", name)?;
+		self.open.push(Kind::Array);
+		Ok(())
+	}
+
+	/// Write an entry of the current table. The key must not be empty, since only tables and
+	/// arrays can be unnamed.
+	pub fn entry(&mut self, key: &str, value: &str) -> Result<(), WriteError> {
+		if self.open.last() == Some(&Kind::Array) {
+			return Err(WriteError::EntryInArray);
+		}
+		if key.is_empty() {
+			return Err(WriteError::EmptyKey);
+		}
+		if value.is_empty() {
+			self.line(&escape(key))
+		} else {
+			self.line(&format!("{} {}", escape(key), escape_value(value)))
+		}
+	}
+
+	/// Write an element of the current array
+	pub fn item(&mut self, value: &str) -> Result<(), WriteError> {
+		if self.open.last() != Some(&Kind::Array) {
+			return Err(WriteError::ItemOutsideArray);
+		}
+		if value.is_empty() {
+			self.inner().write_all(b"\n")?;
+			Ok(())
+		} else {
+			self.line(&escape_value(value))
+		}
+	}
+
+	/// Close the current table or array
+	pub fn end(&mut self) -> Result<(), WriteError> {
+		let delimiter = match self.open.pop() {
+			Some(Kind::Array) => "]",
+			Some(_) => "}",
+			None => return Err(WriteError::ExcessiveEnd),
+		};
+		self.line(delimiter)
+	}
+
+	/// Close all tables and arrays with `$`
+	pub fn end_all(&mut self) -> Result<(), WriteError> {
+		if !self.open.is_empty() {
+			self.open.clear();
+			self.inner().write_all(b"$\n")?;
+		}
+		Ok(())
+	}
+
+	/// The number of tables and arrays that are open
+	pub fn depth(&self) -> usize {
+		self.open.len()
+	}
+
+	/// Flush the underlying writer
+	pub fn flush(&mut self) -> Result<(), WriteError> {
+		self.inner().flush()?;
+		Ok(())
+	}
+
+	/// Finish the document and return the underlying writer. Every table and array must be
+	/// closed, either with `end` or with `end_all`.
+	pub fn finish(mut self) -> Result<W, WriteError> {
+		if !self.open.is_empty() {
+			return Err(WriteError::Unclosed(self.open.len()));
+		}
+		let mut writer = self.writer.take().expect("the writer is present until it is finished");
+		writer.flush()?;
+		Ok(writer)
+	}
+
+	fn inner(&mut self) -> &mut W {
+		self.writer.as_mut().expect("the writer is present until it is finished")
+	}
+
+	fn opening(&mut self, delimiter: &str, name: &str) -> Result<(), WriteError> {
+		if name.is_empty() {
+			self.line(delimiter)
+		} else {
+			self.line(&format!("{} {}", delimiter, escape(name)))
+		}
+	}
+
+	/// Write a line indented by the current depth, where closing delimiters are indented as
+	/// much as their opening delimiters
+	fn line(&mut self, line: &str) -> Result<(), WriteError> {
+		let depth = self.open.len();
+		let writer = self.inner();
+		for _ in 0..depth {
+			writer.write_all(b"\t")?;
+		}
+		writer.write_all(line.as_bytes())?;
+		writer.write_all(b"\n")?;
+		Ok(())
+	}
+}
+
+impl<W: Write> Drop for AconWriter<W> {
+	fn drop(&mut self) {
+		if self.writer.is_some() {
+			let _ = self.end_all();
+			let _ = self.inner().flush();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::panic;
+	use Acon;
+
+	#[test]
+	fn matches_display() {
+		let input = "key  two words \n{ t(46)able\n\tempty\n\t[ list\n\t\t(35) not a comment\n\n\t\t{ named\n\t\t\tx y\n\t\t}\n\t]\n}\n";
+		let acon = input.parse::<Acon>().unwrap();
+		let mut writer = AconWriter::new(Vec::new());
+		writer.entry("key", "two words").unwrap();
+		writer.begin_table("t.able").unwrap();
+		writer.entry("empty", "").unwrap();
+		writer.begin_array("list").unwrap();
+		writer.item("# not a comment").unwrap();
+		writer.item("").unwrap();
+		writer.begin_table("named").unwrap();
+		writer.entry("x", "y").unwrap();
+		writer.end().unwrap();
+		writer.end().unwrap();
+		writer.end().unwrap();
+		let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+		assert_eq!(output, acon.to_string());
+	}
+
+	#[test]
+	fn validates_nesting() {
+		let mut writer = AconWriter::new(Vec::new());
+		assert!(matches!(writer.item("value"), Err(WriteError::ItemOutsideArray)));
+		assert!(matches!(writer.end(), Err(WriteError::ExcessiveEnd)));
+		assert!(matches!(writer.entry("", "value"), Err(WriteError::EmptyKey)));
+		assert!(matches!(writer.entry("", ""), Err(WriteError::EmptyKey)));
+		writer.begin_array("").unwrap();
+		assert!(matches!(writer.entry("key", "value"), Err(WriteError::EntryInArray)));
+		assert_eq!(writer.depth(), 1);
+		assert!(matches!(writer.finish(), Err(WriteError::Unclosed(1))));
+	}
+
+	#[test]
+	fn closes_on_panic() {
+		let mut output = Vec::new();
+		let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+			let mut writer = AconWriter::new(&mut output);
+			writer.begin_table("a").unwrap();
+			writer.begin_array("b").unwrap();
+			writer.item("c").unwrap();
+			panic!("the writer crashed");
+		}));
+		assert!(result.is_err());
+		let output = String::from_utf8(output).unwrap();
+		assert_eq!(output, "{ a\n\t[ b\n\t\tc\n$\n");
+		assert_eq!(output.parse::<Acon>().unwrap().path("a.b.0").unwrap().string(), "c");
+	}
+}